@@ -1,31 +1,60 @@
 use crate::commands::*;
-use crate::rofi_picker;
+use crate::picker;
+use crate::config;
+use crate::clipboard;
 use crate::string_cleaner;
 use crate::filter;
+use crate::sort;
+use crate::fulltext;
 use crate::bibtex;
-use json;
+use crate::ris;
+use crate::citation;
+use crate::template::Template;
+use crate::tui;
+use crate::reading;
+use crate::timestamps;
+use crate::journal;
+use crate::versioning;
+use crate::storage::{self, Library, Storage};
+use crate::sqlite;
+use crate::lint;
+use crate::normalize;
+use crate::venues::{self, Venues};
+use crate::latex_encoder;
+use crate::site;
+use crate::stats;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::io::Read;
 use structopt::clap;
 use structopt::StructOpt;
 
 pub struct App {
-    db: json::JsonValue,
+    db: Library,
     selection: json::JsonValue,
+    config: json::JsonValue,
 }
 
 impl App {
     fn new() -> App {
-        let db = App::load_db();
-        let selection = db.clone();
+        let config = config::load_config(&App::library_file("config.json"));
+        let name = config["storage"].as_str().unwrap_or("json").to_string();
+        let db = Library::new(App::storage(&name, &config));
         App {
-            db: db, selection: selection
+            db, selection: json::object!{}, config
         }
     }
 
     pub fn run() {
-        let app = App::new();
+        let mut app = App::new();
         let args = Opt::from_args();
+        // Start from only the papers a first `by` can match when the storage
+        // finds them without loading the whole library.
+        let matching = match &args.command {
+            Command::By(params) => app.db.storage().select(&params.field, &params.value),
+            _ => None,
+        };
+        app.selection = matching.unwrap_or_else(|| app.db.clone());
         app.match_command(args.command)
     }
 
@@ -41,42 +70,108 @@ impl App {
         }
     }
 
-    fn save_db(&self) {
-        let db_dir = App::save_file_name();
-        let result = std::fs::write(db_dir, self.db.dump());
-        match result {
-            Ok(_) => return,
-            Err(error) => panic!(format!("Could not save database file: {:?}", error))
+    fn library_file(name: &str) -> PathBuf {
+        App::save_file_name().with_file_name(name)
+    }
+
+    /// Save the database, recording what changed since the last save in the
+    /// journal.
+    pub(crate) fn save_db(&self) {
+        let command = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+        let operation = journal::record(&App::library_file("journal.jsonl"), &self.db.saved(), &self.db, &command, &[]);
+        self.write_db();
+        self.commit(operation)
+    }
+
+    fn git_enabled(&self) -> bool {
+        self.config["git"].as_bool().unwrap_or(false)
+    }
+
+    fn library_dir() -> PathBuf {
+        App::save_file_name().parent().expect("Database file has no directory").to_path_buf()
+    }
+
+    /// Commit a change to the database when the library is versioned with git.
+    fn commit(&self, operation: Option<json::JsonValue>) {
+        if let (true, Some(operation)) = (self.git_enabled(), operation) {
+            self.commit_files(&versioning::commit_message(&operation));
         }
     }
 
-    fn load_db() -> json::JsonValue{
-        let db_dir = App::save_file_name();
-        let json_result = std::fs::read_to_string(&db_dir);
-        match json_result {
-            Ok(json) => json::parse(&json).unwrap(),
-            Err(error) => {
-                match error.kind() {
-                    std::io::ErrorKind::NotFound => json::object!{},
-                    _ => panic!(format!("Could not open database file: {:?}", error))
-                }
-            }
+    fn write_db(&self) {
+        self.db.save()
+    }
+
+    fn storage(name: &str, config: &json::JsonValue) -> Box<dyn Storage> {
+        let stable = config["git"].as_bool().unwrap_or(false);
+        match name {
+            "json" => Box::new(storage::JsonFile::new(App::save_file_name(), stable)),
+            "directory" => {
+                let format = match config["entry_format"].as_str().unwrap_or("json") {
+                    "bib" => storage::EntryFormat::Bib,
+                    "json" => storage::EntryFormat::Json,
+                    other => panic!("Unknown entry format: {}", other),
+                };
+                Box::new(storage::Directory::new(App::library_file("entries"), format))
+            },
+            // Git can neither diff nor merge the binary database.
+            "sqlite" if stable => panic!("Git versioning needs the json or directory storage, not sqlite"),
+            "sqlite" => Box::new(sqlite::Sqlite::open(App::library_file("library.sqlite"))),
+            other => panic!("Unknown storage: {}", other),
         }
     }
 
+    /// Commit the stored library along with the journal, notes and venues.
+    fn commit_files(&self, message: &str) {
+        let path = self.storage_path();
+        versioning::commit(&App::library_dir(), &[&path, "journal.jsonl", "notes", "venues.json"], message);
+    }
+
+    /// Path of the stored library relative to the library directory.
+    fn storage_path(&self) -> String {
+        let path = self.db.storage().path();
+        path.strip_prefix(App::library_dir()).unwrap_or(path).to_string_lossy().to_string()
+    }
+
     fn match_command(self, command: Command) {
         match command {
             Command::Add => self.add(),
             Command::Remove => self.remove(),
             Command::BibtexFile(params) => self.bibtex_file(params),
             Command::Bibtex(params) => self.bibtex_input(params),
-            Command::Export => self.export(),
+            Command::RisFile(params) => self.ris_file(params),
+            Command::Ris(params) => self.ris_input(params),
+            Command::Export(params) => self.export(params),
             Command::Print => self.print(),
+            Command::Format(params) => self.format(params),
             Command::List(params) => self.list(params),
             Command::Update(params) => self.update(params),
+            Command::Mark(params) => self.update(UpdateCmd { field: String::from("status"), value: params.status }),
+            Command::Rate(params) => self.update(UpdateCmd { field: String::from("rating"), value: params.rating }),
+            Command::Note => self.note(),
             Command::By(params) => self.filter_by(params),
+            Command::Find(params) => self.find(params),
+            Command::Index(params) => self.index(params),
+            Command::Search(params) => self.search(params),
+            Command::Sort(params) => self.sort(params),
+            Command::Head(params) => self.limit(params, false),
+            Command::Tail(params) => self.limit(params, true),
+            Command::Recent(params) => self.recent(params),
+            Command::AddedSince(params) => self.added_since(params),
             Command::Open => self.open(),
+            Command::CopyKey => self.copy_key(),
             Command::Pick(params) => self.pick(params),
+            Command::AddTag(params) => self.add_tag(params),
+            Command::RemoveTag(params) => self.remove_tag(params),
+            Command::Edit => self.edit(),
+            Command::Tui => self.tui(),
+            Command::Log(params) => self.log(params),
+            Command::Undo(params) => self.undo(params),
+            Command::Sync(params) => self.sync(params),
+            Command::Migrate(params) => self.migrate(params),
+            Command::Lint(params) => self.lint(params),
+            Command::Site(params) => self.site(params),
+            Command::Stats(params) => self.stats(params),
         }
     }
 
@@ -92,8 +187,16 @@ impl App {
         println!("Adding");
         println!("{:#}", self.selection);
         //TODO: confirm
+        let now = timestamps::now();
         for (key, paper) in self.selection.entries() {
-            self.db[key] = paper.clone()
+            let added = match (&self.db[key][timestamps::ADDED], &paper[timestamps::ADDED]) {
+                (json::JsonValue::Null, json::JsonValue::Null) => json::from(now.as_str()),
+                (json::JsonValue::Null, added) => added.clone(),
+                (added, _) => added.clone(),
+            };
+            self.db[key] = paper.clone();
+            self.db[key][timestamps::ADDED] = added;
+            self.db[key][timestamps::MODIFIED] = json::from(now.as_str());
         }
         self.save_db()
     }
@@ -102,75 +205,357 @@ impl App {
         println!("Removing");
         println!("{:#}", self.selection);
         //TODO: confirm
+        self.remove_selected();
+        self.save_db()
+    }
+
+    pub(crate) fn remove_selected(&mut self) {
         for (key, _) in self.selection.entries() {
             self.db.remove(key);
         }
+    }
+
+    fn add_tag(mut self, params: TagCmd) {
+        self.tag_selected(&params.tag);
+        self.save_db()
+    }
+
+    fn remove_tag(mut self, params: TagCmd) {
+        self.untag_selected(&params.tag);
+        self.save_db()
+    }
+
+    fn selected_tags(&self, key: &str) -> Vec<String> {
+        match &self.db[key]["tags"] {
+            json::JsonValue::Array(tags) => tags.iter().map(|tag| tag.to_string()).collect(),
+            json::JsonValue::Null => Vec::new(),
+            tags => vec![tags.to_string()],
+        }
+    }
+
+    pub(crate) fn tag_selected(&mut self, tag: &str) {
+        let keys = self.selection.entries().map(|(key, _)| key.to_string()).collect::<Vec<_>>();
+        for key in keys {
+            if !self.db.has_key(&key) {
+                continue
+            }
+            let mut tags = self.selected_tags(&key);
+            if !tags.iter().any(|existing| existing == tag) {
+                tags.push(tag.to_string());
+                self.db[key.as_str()][timestamps::MODIFIED] = json::from(timestamps::now());
+            }
+            self.db[key.as_str()]["tags"] = json::from(tags);
+        }
+    }
+
+    pub(crate) fn untag_selected(&mut self, tag: &str) {
+        let keys = self.selection.entries().map(|(key, _)| key.to_string()).collect::<Vec<_>>();
+        for key in keys {
+            if self.db[key.as_str()]["tags"].is_null() {
+                continue
+            }
+            let tags = self.selected_tags(&key);
+            if !tags.iter().any(|existing| existing == tag) {
+                continue
+            }
+            let tags = tags.into_iter()
+                .filter(|existing| existing != tag)
+                .collect::<Vec<_>>();
+            self.db[key.as_str()]["tags"] = json::from(tags);
+            self.db[key.as_str()][timestamps::MODIFIED] = json::from(timestamps::now());
+        }
+    }
+
+    fn edit(mut self) {
+        if let Err(error) = self.edit_selected() {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
         self.save_db()
     }
 
+    /// Open the selection as BibTeX in $EDITOR and replace the selected papers
+    /// with the edited entries. When the edited file does not parse, nothing
+    /// is changed and the file is kept so that the edits are not lost.
+    pub(crate) fn edit_selected(&mut self) -> Result<(), String> {
+        // Files kept from failed edits are not overwritten.
+        let file_name = (1..)
+            .map(|count| std::env::temp_dir().join(format!("paperman-{}-{}.bib", std::process::id(), count)))
+            .find(|file_name| !file_name.exists())
+            .expect("No temporary file name left");
+        std::fs::write(&file_name, bibtex::generate_stored_bibtex(self.selection.clone(), bibtex::Dialect::Bibtex))
+            .expect("Failed to write temporary file");
+        let editor = std::env::var("EDITOR").unwrap_or_else(|_| String::from("vi"));
+        std::process::Command::new(&editor)
+            .arg(&file_name)
+            .status()
+            .expect("Failed to open editor");
+        let bibtex_string = std::fs::read_to_string(&file_name).expect("Failed to read edited file");
+        let mut edited = bibtex::try_parse_bibtex(&bibtex_string).map_err(|error| format!(
+            "Failed to parse bibtex: {}, the edits are kept in {}", error, file_name.display()
+        ))?;
+        let _ = std::fs::remove_file(&file_name);
+
+        for (key, paper) in edited.entries_mut() {
+            // Lists such as tags were written comma separated.
+            for (field, value) in self.selection[key].entries() {
+                if value.is_array() && paper[field].is_string() {
+                    let items = paper[field].to_string().split(',')
+                        .map(str::trim)
+                        .filter(|item| !item.is_empty())
+                        .map(String::from)
+                        .collect::<Vec<_>>();
+                    paper[field] = json::from(items);
+                }
+            }
+        }
+        self.remove_selected();
+        let now = timestamps::now();
+        for (key, paper) in edited.entries_mut() {
+            if paper[timestamps::ADDED].is_null() {
+                paper[timestamps::ADDED] = json::from(now.as_str());
+            }
+            paper[timestamps::MODIFIED] = json::from(now.as_str());
+            self.db[key] = paper.clone();
+        }
+        self.selection = edited;
+        return Ok(())
+    }
+
+    fn note(&self) {
+        let notes = reading::note_files(&App::library_file("notes"), &self.db);
+        for (key, paper) in self.selection.entries() {
+            reading::edit_note(&notes[key], key, paper);
+        }
+        if self.git_enabled() {
+            self.commit_files("Edit notes");
+        }
+    }
+
+    fn tui(mut self) {
+        tui::run(&mut self);
+    }
+
+    pub(crate) fn selection(&self) -> &json::JsonValue {
+        &self.selection
+    }
+
+    pub(crate) fn db(&self) -> &json::JsonValue {
+        &self.db
+    }
+
+    /// Markdown notes of the papers by key.
+    pub(crate) fn notes(&self) -> HashMap<String, String> {
+        reading::load_notes(&App::library_file("notes"), &self.db)
+    }
+
+    /// Select a single paper, taking it from the database when present.
+    pub(crate) fn select(&mut self, key: &str, paper: &json::JsonValue) {
+        let mut selection = json::object!{};
+        selection[key] = if self.db.has_key(key) { self.db[key].clone() } else { paper.clone() };
+        self.selection = selection;
+    }
+
     fn update(mut self, params: UpdateCmd) {
+        if let Err(error) = reading::validate(&params.field, &params.value) {
+            panic!("{}", error);
+        }
         let json_value = json::from(params.value);
+        let now = json::from(timestamps::now());
         for (key, _) in self.selection.entries() {
             self.db[key][&params.field] = json_value.clone();
+            self.db[key][timestamps::MODIFIED] = now.clone();
         }
         self.save_db()
     }
 
     fn bibtex_file(mut self, params: BibtexFileCmd) {
         let bibtex_string = std::fs::read_to_string(&params.bibtex).expect("Failed to read file");
-        self.selection = bibtex::parse_bibtex(&bibtex_string);
+        self.selection = self.normalize(bibtex::parse_bibtex(&bibtex_string));
         self.parse_remaining_args(params.remaining_args);
     }
 
     fn bibtex_input(mut self, params: BibtexInputCmd) {
         let mut bibtex_string = String::new();
         std::io::stdin().read_to_string(&mut bibtex_string).expect("Failed to read stdin");
-        self.selection = bibtex::parse_bibtex(&bibtex_string);
+        self.selection = self.normalize(bibtex::parse_bibtex(&bibtex_string));
+        self.parse_remaining_args(params.remaining_args);
+    }
+
+    fn ris_file(mut self, params: RisFileCmd) {
+        let ris_string = std::fs::read_to_string(&params.ris).expect("Failed to read file");
+        self.selection = self.normalize(ris::parse_ris(&ris_string));
+        self.parse_remaining_args(params.remaining_args);
+    }
+
+    fn ris_input(mut self, params: RisInputCmd) {
+        let mut ris_string = String::new();
+        std::io::stdin().read_to_string(&mut ris_string).expect("Failed to read stdin");
+        self.selection = self.normalize(ris::parse_ris(&ris_string));
         self.parse_remaining_args(params.remaining_args);
     }
 
-    fn export(self) {
-        print!("{}", bibtex::generate_bibtex(self.selection));
+    /// Run the import normalizers enabled in the config.
+    fn normalize(&self, imported: json::JsonValue) -> json::JsonValue {
+        normalize::normalize(imported, &normalize::from_config(&self.config))
+    }
+
+    fn export(self, params: ExportCmd) {
+        self.export_selected(&params);
+    }
+
+    pub(crate) fn export_selected(&self, params: &ExportCmd) {
+        let selection = match params.venue {
+            Some(style) => self.venues().rename(self.selection.clone(), style),
+            None => self.selection.clone(),
+        };
+        let selection = if params.ascii { latex_encoder::encode_selection(selection) } else { selection };
+        let exported = match params.format.as_str() {
+            "ris" => ris::generate_ris(selection),
+            _ => bibtex::generate_bibtex(selection, params.dialect),
+        };
+        if params.clipboard {
+            clipboard::copy(&exported);
+        } else {
+            print!("{}", exported);
+        }
     }
 
     fn print(&self) {
         println!("{:#}", self.selection)
     }
 
+    fn format(self, params: FormatCmd) {
+        let markup = if params.html {
+            citation::Markup::Html
+        } else if params.markdown {
+            citation::Markup::Markdown
+        } else {
+            citation::Markup::Plain
+        };
+        match params.csl {
+            Some(csl) => print!("{}", citation::format_with_csl(self.selection, &csl, markup)),
+            None => print!("{}", citation::format_references(&self.selection, params.style, markup)),
+        }
+    }
+
     fn list(&self, params: ListCmd) {
-        for (_key, paper) in self.selection.entries() {
-            let decoded = string_cleaner::clean_string(paper[&params.field].as_str().unwrap());
-            println!("{}", decoded);
+        let template = match (params.format, params.field) {
+            (Some(template), _) => template,
+            (None, Some(field)) => Template::field(&field),
+            (None, None) => "short".parse().unwrap(),
+        };
+        for (key, paper) in self.selection.entries() {
+            println!("{}", template.render(key, paper));
         }
     }
 
-    fn open(&self) {
-        for (_, paper) in self.selection.entries(){
-            let file_name = paper["file"].clone();
+    fn open(mut self) {
+        self.open_selected();
+        self.save_db()
+    }
+
+    pub(crate) fn open_selected(&mut self) {
+        let now = timestamps::now();
+        for (key, paper) in self.selection.entries(){
+            let file_name = match paper["file"].as_str() {
+                Some(file_name) => file_name,
+                None => {
+                    println!("No file for {}", key);
+                    continue
+                }
+            };
             println!("Opening {}", &file_name);
+            // The viewer is left running after paperman exits.
+            #[allow(clippy::zombie_processes)]
             std::process::Command::new("xdg-open")
-                .arg(file_name.as_str()
-                .unwrap()).spawn()
+                .arg(file_name).spawn()
                 .expect("Failed to open file.");
+            if self.db.has_key(key) {
+                self.db[key][timestamps::LAST_OPENED] = json::from(now.as_str());
+            }
         }
     }
 
     fn pick(mut self, params: PickCmd) {
-        let selection = rofi_picker::pick(self.selection);
+        let name = params.picker
+            .or_else(|| self.config["picker"].as_str().map(String::from))
+            .unwrap_or_else(|| String::from("auto"));
+        let picker = match name.as_str() {
+            "auto" => picker::detect_picker(),
+            name => picker::picker_by_name(name),
+        };
+        let template = match params.format {
+            Some(template) => template,
+            None => self.config["pick_format"].as_str().unwrap_or(picker::DEFAULT_FORMAT)
+                .parse().expect("Invalid pick_format in config"),
+        };
+        let actions = self.pick_actions();
+        let keybindings = actions.iter().map(|(keybinding, _)| keybinding.clone()).collect::<Vec<_>>();
+
+        let (selection, keybinding) = picker::pick(self.selection, picker.as_ref(), &template, &keybindings);
+        if selection.is_empty() {
+            return
+        }
         self.selection = selection;
-        self.parse_remaining_args(params.remaining_args);
+        match keybinding.and_then(|index| actions.get(index)) {
+            Some((_, action)) => {
+                let action = action.split_whitespace().map(String::from).collect();
+                self.parse_remaining_args(action)
+            },
+            None => self.parse_remaining_args(params.remaining_args),
+        }
+    }
+
+    /// Custom menu keybindings and the commands they run on the picked papers.
+    fn pick_actions(&self) -> Vec<(String, String)> {
+        let actions = &self.config["pick_keybindings"];
+        if actions.is_null() {
+            return vec![
+                (String::from("Alt+o"), String::from("open")),
+                (String::from("Alt+e"), String::from("export")),
+                (String::from("Alt+c"), String::from("copy-key")),
+            ]
+        }
+        return actions.entries()
+            .map(|(keybinding, command)| (keybinding.to_string(), command.to_string()))
+            .collect()
+    }
+
+    fn copy_key(&self) {
+        let keys = self.selection.entries().map(|(key, _)| key).collect::<Vec<_>>().join(",");
+        clipboard::copy(&keys);
+        println!("Copied {}", keys);
+    }
+
+    fn venues(&self) -> Venues {
+        Venues::load(&App::library_file("venues.json"))
     }
 
     fn filter_by(mut self, params: ByCmd) {
-        let field = &params.field;
-        let value = &json::from(params.value);
-        let clean = match field.as_str() {
-            "title" => true,
-            "author" => true,
-            _ => false
+        let field = params.field.as_str();
+        let value = &json::from(params.value.as_str());
+        let matching = if filter::EXACT_FIELDS.contains(&field) {
+            filter::Matching::Exact
+        } else if matches!(field, "title" | "author" | "venue") || venues::VENUE_FIELDS.contains(&field) {
+            filter::Matching::Cleaned
+        } else {
+            filter::Matching::Contains
         };
+        // `venue` stands for any of the venue fields, which also match other
+        // names of the same venue.
+        let (fields, venue_field) = match field {
+            "venue" => (venues::VENUE_FIELDS.to_vec(), Some(None)),
+            field if venues::VENUE_FIELDS.contains(&field) => (vec![field], Some(Some(field))),
+            field => (vec![field], None),
+        };
+        let venues = self.venues();
         let to_remove = self.selection.entries()
-            .filter(|(_, paper)| !filter::match_values(value, &paper[field], clean))
+            .filter(|(_, paper)| {
+                let matches = fields.iter().any(|field| filter::match_values(value, &reading::field(paper, field), matching))
+                    || venue_field.is_some_and(|venue_field| venues.published_in(paper, venue_field, &params.value));
+                !matches
+            })
             .map(|(key, _)| key.to_string())
             .collect::<Vec<_>>();
 
@@ -179,5 +564,229 @@ impl App {
         }
         self.parse_remaining_args(params.remaining_args);
     }
-}
 
+    fn sort(mut self, params: SortCmd) {
+        self.selection = sort::sort_selection(self.selection, &params.field, params.desc);
+        self.parse_remaining_args(params.remaining_args);
+    }
+
+    fn limit(mut self, params: LimitCmd, from_end: bool) {
+        self.selection = sort::limit_selection(self.selection, params.count, from_end);
+        self.parse_remaining_args(params.remaining_args);
+    }
+
+    fn select_ranked(&mut self, ranked: &[(String, f64)]) {
+        let mut selection = json::object!{};
+        for (key, _) in ranked {
+            selection[key.as_str()] = self.selection[key.as_str()].clone();
+        }
+        self.selection = selection;
+    }
+
+    fn find(mut self, params: FindCmd) {
+        let ranked = filter::rank_fuzzy(&self.selection, &self.notes(), &params.query, params.limit);
+        if params.remaining_args.is_empty() {
+            for (key, score) in ranked {
+                println!("{}\t{:.2}\t{}", key, score, string_cleaner::clean_string(self.selection[&key]["title"].as_str().unwrap_or("")));
+            }
+            return
+        }
+        self.select_ranked(&ranked);
+        self.parse_remaining_args(params.remaining_args);
+    }
+
+    fn index(&self, params: IndexCmd) {
+        let index_file = App::library_file("fulltext.json");
+        let text_dir = App::library_file("fulltext");
+        let mut index = fulltext::load_index(&index_file);
+        for (key, paper) in self.selection.entries() {
+            if let Some(file) = paper["file"].as_str() {
+                match fulltext::index_paper(&mut index, &text_dir, key, &PathBuf::from(file), params.force) {
+                    Ok(true) => println!("Indexed {}", key),
+                    Ok(false) => (),
+                    Err(error) => eprintln!("Skipping {}: {}", key, error),
+                }
+            }
+        }
+        fulltext::save_index(&index_file, &index);
+    }
+
+    fn search(mut self, params: SearchCmd) {
+        let text_dir = App::library_file("fulltext");
+        let index = fulltext::load_index(&App::library_file("fulltext.json"));
+        let ranked = fulltext::rank(&index, &self.selection, &params.query);
+        if params.remaining_args.is_empty() {
+            for (key, score) in ranked {
+                let text = fulltext::load_text(&index, &text_dir, &key).unwrap_or_default();
+                println!("{}\t{:.2}\t{}", key, score, string_cleaner::clean_string(self.selection[&key]["title"].as_str().unwrap_or("")));
+                println!("    {}", fulltext::snippet(&text, &params.query));
+            }
+            return
+        }
+        self.select_ranked(&ranked);
+        self.parse_remaining_args(params.remaining_args);
+    }
+
+    fn recent(mut self, params: RecentCmd) {
+        let field = match params.by.as_str() {
+            "modified" => timestamps::MODIFIED,
+            "opened" => timestamps::LAST_OPENED,
+            _ => timestamps::ADDED,
+        };
+        let to_remove = self.selection.entries()
+            .filter(|(_, paper)| paper[field].is_null())
+            .map(|(key, _)| key.to_string())
+            .collect::<Vec<_>>();
+        for key in to_remove {
+            self.selection.remove(&key);
+        }
+        let sorted = sort::sort_selection(self.selection, field, true);
+        self.selection = sort::limit_selection(sorted, params.count, false);
+        self.parse_remaining_args(params.remaining_args);
+    }
+
+    fn added_since(mut self, params: AddedSinceCmd) {
+        let to_remove = self.selection.entries()
+            .filter(|(_, paper)| match paper[timestamps::ADDED].as_str() {
+                Some(added) => added < params.date.as_str(),
+                None => true,
+            })
+            .map(|(key, _)| key.to_string())
+            .collect::<Vec<_>>();
+        for key in to_remove {
+            self.selection.remove(&key);
+        }
+        self.parse_remaining_args(params.remaining_args);
+    }
+
+    fn log(&self, params: LogCmd) {
+        let operations = journal::load(&App::library_file("journal.jsonl"));
+        for operation in operations.iter().rev().take(params.count) {
+            println!("{}", journal::describe(operation));
+        }
+    }
+
+    fn undo(mut self, params: UndoCmd) {
+        let journal_file = App::library_file("journal.jsonl");
+        let operations = journal::load(&journal_file);
+        let to_undo = journal::undoable(&operations, params.count);
+        if to_undo.is_empty() {
+            println!("Nothing to undo");
+            return
+        }
+        let before = self.db.clone();
+        let mut undone = Vec::new();
+        for operation in to_undo {
+            println!("Undoing {}", journal::describe(operation));
+            journal::revert(&mut self.db, operation);
+            undone.extend(operation["id"].as_usize());
+        }
+        let command = std::env::args().skip(1).collect::<Vec<_>>().join(" ");
+        let operation = journal::record(&journal_file, &before, &self.db, &command, &undone);
+        self.write_db();
+        self.commit(operation)
+    }
+
+    fn sync(self, params: SyncCmd) {
+        if !self.git_enabled() {
+            panic!("Syncing needs a git-versioned library, set \"git\": true in config.json");
+        }
+        let remote = params.remote
+            .or_else(|| self.config["git_remote"].as_str().map(String::from))
+            .unwrap_or_else(|| String::from("origin"));
+        self.db.storage().save(&self.db);
+        let path = self.storage_path();
+        self.commit_files("Update library");
+        // A single database file is merged per paper, while with one file per
+        // paper git already merges papers separately.
+        let merge_file = if self.db.storage().path().is_dir() { None } else { Some(path.as_str()) };
+        versioning::sync(&App::library_dir(), &remote, merge_file);
+    }
+
+    fn migrate(self, params: MigrateCmd) {
+        let target = App::storage(&params.storage, &self.config);
+        if target.path() == self.db.storage().path() {
+            panic!("The library is already stored in {:?}", target.path());
+        }
+        target.save(&self.db);
+        println!("Copied {} papers to {:?}", self.db.len(), target.path());
+        println!("Set \"storage\": \"{}\" in config.json to use it", params.storage);
+    }
+
+    /// Report problems in the selected papers, exiting with an error if any
+    /// are left unfixed.
+    fn lint(mut self, params: LintCmd) {
+        let mut remaining = 0;
+        let mut fixed = false;
+        let now = json::from(timestamps::now());
+        let keys = self.selection.entries().map(|(key, _)| key.to_string()).collect::<Vec<_>>();
+        for key in keys {
+            let problems = lint::lint_paper(&key, &self.selection[key.as_str()], params.dialect, params.strict);
+            // Papers outside the library, as from bibtex-file, have nowhere
+            // to write their fixes.
+            let fixing = params.fix && self.db.has_key(&key);
+            for problem in &problems {
+                let fix = match (&problem.fix, fixing) {
+                    (Some(_), true) => " (fixed)",
+                    (Some(_), false) => " (fixable)",
+                    (None, _) => "",
+                };
+                println!("{}: {}{}", problem.key, problem.message, fix);
+            }
+            remaining += problems.iter().filter(|problem| problem.fix.is_none() || !fixing).count();
+            if fixing && problems.iter().any(|problem| problem.fix.is_some()) {
+                lint::apply_fixes(&mut self.selection[key.as_str()], &problems);
+                lint::apply_fixes(&mut self.db[key.as_str()], &problems);
+                self.db[key.as_str()][timestamps::MODIFIED] = now.clone();
+                fixed = true;
+            }
+        }
+        if fixed {
+            self.save_db();
+        }
+        if remaining > 0 {
+            eprintln!("{} problems found", remaining);
+            std::process::exit(1);
+        }
+    }
+
+    /// Write the selection as a publication page, copying the PDFs of the
+    /// papers beside it.
+    fn site(&self, params: SiteCmd) {
+        let templates = site::Templates::load(params.templates.as_deref());
+        let pdf_dir = params.out.join("pdf");
+        std::fs::create_dir_all(&params.out).expect("Could not create site directory");
+        let pdf_paths = site::pdf_paths(&self.selection);
+        let mut published = Vec::new();
+        for (key, paper) in self.selection.entries() {
+            let file = match paper["file"].as_str().map(PathBuf::from) {
+                Some(file) if file.exists() => file,
+                _ => continue,
+            };
+            std::fs::create_dir_all(&pdf_dir).expect("Could not create PDF directory");
+            std::fs::copy(&file, params.out.join(&pdf_paths[key])).expect("Could not copy PDF");
+            published.push(key.to_string());
+        }
+        let page = site::render_site(
+            &self.selection, &templates, params.group_by, params.style, &params.title,
+            &|key| published.iter().any(|published| published == key),
+        );
+        std::fs::write(params.out.join("index.html"), page).expect("Could not write index.html");
+        std::fs::write(params.out.join("style.css"), &templates.style).expect("Could not write style.css");
+        println!("Wrote {} papers to {}", self.selection.len(), params.out.join("index.html").display());
+    }
+
+    fn stats(&self, params: StatsCmd) {
+        let fields = if params.fields.is_empty() {
+            stats::DEFAULT_FIELDS.to_vec()
+        } else {
+            params.fields.iter().map(String::as_str).collect()
+        };
+        let stats = stats::stats(&self.selection, &fields, params.top, &self.venues());
+        if params.json {
+            println!("{:#}", stats);
+        } else {
+            print!("{}", stats::format_table(&stats, params.fields.is_empty()));
+        }
+    }
+}
@@ -1,28 +1,109 @@
 use nom_bibtex::Bibtex;
+use std::str::FromStr;
 
-pub fn generate_bibtex(selection: json::JsonValue) -> String {
+/// The BibTeX flavour used when exporting. Entries are always stored in the
+/// classic BibTeX form internally.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dialect {
+    Bibtex,
+    Biblatex,
+}
+
+impl FromStr for Dialect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Dialect, String> {
+        match s {
+            "bibtex" => Ok(Dialect::Bibtex),
+            "biblatex" => Ok(Dialect::Biblatex),
+            _ => Err(format!("Unknown dialect: {}", s)),
+        }
+    }
+}
+
+// BibLaTeX field names and their classic BibTeX counterparts.
+const FIELD_ALIASES: [(&str, &str); 5] = [
+    ("journaltitle", "journal"),
+    ("location", "address"),
+    ("eprinttype", "archiveprefix"),
+    ("eprintclass", "primaryclass"),
+    ("annotation", "annote"),
+];
+
+// BibLaTeX entry types without a classic counterpart of the same name.
+const ENTRY_TYPE_ALIASES: [(&str, &str); 7] = [
+    ("online", "misc"),
+    ("electronic", "misc"),
+    ("www", "misc"),
+    ("mvbook", "book"),
+    ("collection", "book"),
+    ("mvproceedings", "proceedings"),
+    ("bookinbook", "inbook"),
+];
+
+pub const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec"
+];
+
+// Lets month macros such as `month = may` parse.
+const MONTH_STRINGS: &str = "@string{jan = \"jan\"} @string{feb = \"feb\"} @string{mar = \"mar\"}
+@string{apr = \"apr\"} @string{may = \"may\"} @string{jun = \"jun\"}
+@string{jul = \"jul\"} @string{aug = \"aug\"} @string{sep = \"sep\"}
+@string{oct = \"oct\"} @string{nov = \"nov\"} @string{dec = \"dec\"}
+";
+
+/// Fields for bookkeeping and local files, kept out of exported BibTeX.
+pub const PRIVATE_FIELDS: [&str; 7] = ["file", "tags", "status", "rating", "added", "modified", "last_opened"];
+
+/// BibTeX of the selection for sharing, without the private fields.
+pub fn generate_bibtex(mut selection: json::JsonValue, dialect: Dialect) -> String {
+    for (_, paper) in selection.entries_mut() {
+        for field in PRIVATE_FIELDS.iter() {
+            paper.remove(field);
+        }
+    }
+    return generate_stored_bibtex(selection, dialect)
+}
+
+/// BibTeX of the selection with all fields, for storing and editing papers.
+pub fn generate_stored_bibtex(selection: json::JsonValue, dialect: Dialect) -> String {
     let mut result = String::new();
     for (citation_key, paper) in selection.entries() {
+        let paper = match dialect {
+            Dialect::Bibtex => to_internal(paper.clone()),
+            Dialect::Biblatex => to_biblatex(to_internal(paper.clone())),
+        };
         result.push_str(&format!("@{}{{{},\n", paper["entry_type"], citation_key));
         for (key, value) in paper.entries() {
-            if key != "entry_type" {
-                result.push_str(&format!("    {} = {{{}}},\n", key, value));
+            if key == "entry_type" {
+                continue
             }
+            let value = match value {
+                json::JsonValue::Array(items) => items.iter().map(|item| item.to_string()).collect::<Vec<_>>().join(", "),
+                value => value.to_string(),
+            };
+            result.push_str(&format!("    {} = {{{}}},\n", key, value));
         }
+        result.push_str("}\n");
     }
-    result.push_str("}\n");
     return result;
 }
 
 pub fn parse_bibtex(bibtex_string: &str) -> json::JsonValue {
-    let bibtex = Bibtex::parse(&bibtex_string).expect("Failed to parse bibtex");
+    return try_parse_bibtex(bibtex_string).unwrap_or_else(|error| panic!("Failed to parse bibtex: {}", error))
+}
+
+/// The papers of a BibTeX string, or why it could not be parsed.
+pub fn try_parse_bibtex(bibtex_string: &str) -> Result<json::JsonValue, String> {
+    let bibtex_string = format!("{}{}", MONTH_STRINGS, bibtex_string);
+    let bibtex = Bibtex::parse(&bibtex_string).map_err(|error| error.to_string())?;
     let mut new_selection = json::object!{};
     for biblio in bibtex.bibliographies(){
         let key = biblio.citation_key();
-        let paper_object = parse_paper(&biblio);
+        let paper_object = parse_paper(biblio);
         new_selection[key] = paper_object;
     }
-    return new_selection
+    return Ok(new_selection)
 }
 
 fn parse_paper(biblio: &nom_bibtex::Bibliography) -> json::JsonValue {
@@ -34,5 +115,244 @@ fn parse_paper(biblio: &nom_bibtex::Bibliography) -> json::JsonValue {
     for (key, value) in biblio.tags(){
         paper_object[key] = json::from(value.clone());
     }
-    paper_object
+    to_internal(paper_object)
+}
+
+/// Month number (1-12) of a month name, abbreviation or number.
+pub fn month_number(month: &str) -> Option<usize> {
+    let month = month.trim().trim_end_matches('.').to_lowercase();
+    if let Ok(number) = month.parse::<usize>() {
+        return if (1..=12).contains(&number) { Some(number) } else { None }
+    }
+    if month.len() < 3 {
+        return None
+    }
+    return MONTHS.iter().position(|name| month.starts_with(name)).map(|index| index + 1)
+}
+
+fn rename_field(paper: &mut json::JsonValue, from: &str, to: &str) {
+    let key = paper.entries()
+        .map(|(key, _)| key.to_string())
+        .find(|key| key.to_lowercase() == from);
+    if let Some(key) = key {
+        let value = paper.remove(&key);
+        if paper[to].is_null() {
+            paper[to] = value;
+        }
+    }
+}
+
+fn split_date(paper: &mut json::JsonValue) {
+    let date = paper.remove("date");
+    let date = match date.as_str() {
+        Some(date) => date.split('/').next().unwrap_or("").trim().to_string(),
+        None => return,
+    };
+    let mut parts = date.split('-');
+    if let Some(year) = parts.next().filter(|year| !year.is_empty()) {
+        if paper["year"].is_null() {
+            paper["year"] = json::from(year);
+        }
+    }
+    if let Some(month) = parts.next().and_then(month_number) {
+        if paper["month"].is_null() {
+            paper["month"] = json::from(MONTHS[month - 1]);
+        }
+    }
+    if let Some(day) = parts.next().and_then(|day| day.parse::<usize>().ok()) {
+        if paper["day"].is_null() {
+            paper["day"] = json::from(day.to_string());
+        }
+    }
+}
+
+/// Normalize a BibTeX or BibLaTeX entry into the internal, classic BibTeX form.
+pub fn to_internal(mut paper: json::JsonValue) -> json::JsonValue {
+    for (biblatex, bibtex) in FIELD_ALIASES.iter() {
+        rename_field(&mut paper, biblatex, bibtex);
+    }
+    // Also lowercases a `Date` key so split_date finds it.
+    rename_field(&mut paper, "date", "date");
+    split_date(&mut paper);
+
+    let entry_type = paper["entry_type"].as_str().unwrap_or("misc").to_lowercase();
+    let thesis_type = paper["type"].as_str().unwrap_or("").to_lowercase();
+    let entry_type = match (entry_type.as_str(), thesis_type.as_str()) {
+        ("thesis", "mathesis") | ("thesis", "mastersthesis") => "mastersthesis",
+        ("thesis", _) => "phdthesis",
+        ("report", _) => "techreport",
+        (entry_type, _) => ENTRY_TYPE_ALIASES.iter()
+            .find(|(biblatex, _)| *biblatex == entry_type)
+            .map(|(_, bibtex)| *bibtex)
+            .unwrap_or(entry_type),
+    }.to_string();
+    if matches!(thesis_type.as_str(), "mathesis" | "mastersthesis" | "phdthesis" | "techreport") {
+        paper.remove("type");
+    }
+    if entry_type.ends_with("thesis") {
+        rename_field(&mut paper, "institution", "school");
+    }
+    paper["entry_type"] = json::from(entry_type);
+    return paper
+}
+
+/// Convert an entry in the internal form to BibLaTeX field names and entry types.
+pub fn to_biblatex(mut paper: json::JsonValue) -> json::JsonValue {
+    for (biblatex, bibtex) in FIELD_ALIASES.iter() {
+        rename_field(&mut paper, bibtex, biblatex);
+    }
+
+    let entry_type = paper["entry_type"].as_str().unwrap_or("misc").to_string();
+    let entry_type = match entry_type.as_str() {
+        "misc" if !paper["url"].is_null() && paper["howpublished"].is_null() => "online",
+        "techreport" => {
+            paper["type"] = json::from("techreport");
+            "report"
+        },
+        "phdthesis" => {
+            paper["type"] = json::from("phdthesis");
+            "thesis"
+        },
+        "mastersthesis" => {
+            paper["type"] = json::from("mathesis");
+            "thesis"
+        },
+        entry_type => entry_type,
+    }.to_string();
+    if entry_type == "thesis" {
+        rename_field(&mut paper, "school", "institution");
+    }
+    paper["entry_type"] = json::from(entry_type);
+
+    if let Some(year) = paper["year"].as_str() {
+        let mut date = year.to_string();
+        if let Some(month) = paper["month"].as_str().and_then(month_number) {
+            date.push_str(&format!("-{:02}", month));
+            if let Some(day) = paper["day"].as_str().and_then(|day| day.parse::<usize>().ok()) {
+                date.push_str(&format!("-{:02}", day));
+                paper.remove("day");
+            }
+            paper.remove("month");
+        }
+        paper.remove("year");
+        paper["date"] = json::from(date);
+    }
+    return paper
+}
+
+/// Fields holding lists of names separated by "and".
+pub const NAME_FIELDS: [&str; 2] = ["author", "editor"];
+
+/// Split a BibTeX name list such as `author` on the top-level `and`s.
+pub fn split_authors(authors: &str) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut current: Vec<&str> = Vec::new();
+    let mut depth = 0;
+    for word in authors.split_whitespace() {
+        if word == "and" && depth == 0 {
+            result.push(current.join(" "));
+            current.clear();
+            continue
+        }
+        depth += word.matches('{').count() as i32 - word.matches('}').count() as i32;
+        current.push(word);
+    }
+    if !current.is_empty() {
+        result.push(current.join(" "));
+    }
+    return result
+}
+
+/// Split a single BibTeX name in either "First Last" or "Last, First" form
+/// into its first and last names.
+pub fn split_name(name: &str) -> (String, String) {
+    match name.find(',') {
+        Some(index) => (name[index + 1..].trim().to_string(), name[..index].trim().to_string()),
+        None => {
+            let mut words = name.split_whitespace().collect::<Vec<_>>();
+            let last = words.pop().unwrap_or("").to_string();
+            (words.join(" "), last)
+        },
+    }
+}
+
+/// Last name of a single BibTeX name in either "First Last" or "Last, First" form.
+pub fn last_name(name: &str) -> String {
+    split_name(name).1
+}
+
+#[test]
+fn import_biblatex_fields() {
+    let selection = parse_bibtex("@online{k, title={T}, Date={2019-05-03}, journaltitle={J}, eprinttype={arXiv}}");
+    let paper = &selection["k"];
+    assert_eq!(paper["entry_type"], "misc");
+    assert_eq!(paper["year"], "2019");
+    assert_eq!(paper["month"], "may");
+    assert_eq!(paper["day"], "3");
+    assert_eq!(paper["journal"], "J");
+    assert_eq!(paper["archiveprefix"], "arXiv");
+    assert!(paper["date"].is_null());
+    assert!(paper["journaltitle"].is_null());
+}
+
+#[test]
+fn import_bibtex_month_macro() {
+    let selection = parse_bibtex("@article{k, title={T}, year={2017}, month = may}");
+    assert_eq!(selection["k"]["month"], "may");
+}
+
+#[test]
+fn import_biblatex_thesis() {
+    let selection = parse_bibtex("@thesis{k, type={mathesis}, institution={Uni}}");
+    assert_eq!(selection["k"]["entry_type"], "mastersthesis");
+    assert_eq!(selection["k"]["school"], "Uni");
+    assert!(selection["k"]["type"].is_null());
+}
+
+#[test]
+fn export_biblatex() {
+    let mut selection = json::object!{};
+    selection["k"] = json::object!{
+        "entry_type": "misc", "url": "http://x", "journal": "J", "year": "2017", "month": "may"
+    };
+    let generated = generate_bibtex(selection, Dialect::Biblatex);
+    assert!(generated.starts_with("@online{k,\n"));
+    assert!(generated.contains("journaltitle = {J},\n"));
+    assert!(generated.contains("date = {2017-05},\n"));
+    assert!(!generated.contains("year"));
+    assert!(generated.ends_with("}\n"));
+}
+
+#[test]
+fn export_without_private_fields() {
+    let selection = json::object!{"k": {
+        "entry_type": "article", "title": "T", "file": "/home/me/t.pdf", "tags": ["dp"],
+        "status": "read", "rating": 5, "added": "2020-01-01T00:00:00Z",
+    }};
+    assert_eq!(generate_bibtex(selection.clone(), Dialect::Bibtex), "@article{k,\n    title = {T},\n}\n");
+    let stored = generate_stored_bibtex(selection, Dialect::Bibtex);
+    assert!(stored.contains("    tags = {dp},\n"));
+    assert!(stored.contains("    rating = {5},\n"));
+}
+
+#[test]
+fn export_round_trip_between_dialects() {
+    let bibtex = "@phdthesis{k, title={T}, school={Uni}, year={2020}, month={jun}}";
+    let selection = parse_bibtex(bibtex);
+    let biblatex = generate_bibtex(selection.clone(), Dialect::Biblatex);
+    assert!(biblatex.starts_with("@thesis{k,\n"));
+    assert_eq!(parse_bibtex(&biblatex), selection);
+    let classic = generate_bibtex(parse_bibtex(&biblatex), Dialect::Bibtex);
+    assert!(classic.starts_with("@phdthesis{k,\n"));
+    assert!(classic.contains("school = {Uni},\n"));
+}
+
+#[test]
+fn month_numbers() {
+    assert_eq!(month_number("May"), Some(5));
+    assert_eq!(month_number("september"), Some(9));
+    assert_eq!(month_number("Sep."), Some(9));
+    assert_eq!(month_number("05"), Some(5));
+    assert_eq!(month_number("13"), None);
+    assert_eq!(month_number("ma"), None);
 }
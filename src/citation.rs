@@ -0,0 +1,394 @@
+use crate::bibtex;
+use crate::latex_decoder;
+use crate::string_cleaner;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Apa,
+    Ieee,
+    Acm,
+}
+
+impl FromStr for Style {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Style, String> {
+        match s {
+            "apa" => Ok(Style::Apa),
+            "ieee" => Ok(Style::Ieee),
+            "acm" => Ok(Style::Acm),
+            _ => Err(format!("Unknown citation style: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Markup {
+    Plain,
+    Markdown,
+    Html,
+}
+
+struct Reference {
+    authors: Vec<(String, String)>,
+    year: String,
+    title: String,
+    venue: String,
+    volume: String,
+    number: String,
+    pages: String,
+    doi: String,
+    url: String,
+}
+
+fn field(paper: &json::JsonValue, name: &str) -> String {
+    match paper[name].as_str() {
+        Some(value) => string_cleaner::clean_string(value),
+        None => String::new(),
+    }
+}
+
+fn venue(paper: &json::JsonValue) -> String {
+    ["journal", "booktitle", "publisher", "school", "institution", "howpublished"].iter()
+        .map(|name| field(paper, name))
+        .find(|value| !value.is_empty())
+        .unwrap_or_default()
+}
+
+impl Reference {
+    // The title is rendered with its emphasis and math, the other fields are
+    // plain text escaped when formatted.
+    fn from_paper(paper: &json::JsonValue, markup: Markup) -> Reference {
+        let authors = match paper["author"].as_str() {
+            Some(authors) => bibtex::split_authors(authors).iter()
+                .map(|name| bibtex::split_name(name))
+                .map(|(first, last)| (string_cleaner::clean_string(&first), string_cleaner::clean_string(&last)))
+                .collect(),
+            None => Vec::new(),
+        };
+        Reference {
+            authors,
+            year: field(paper, "year"),
+            title: paper["title"].as_str()
+                .map(|title| string_cleaner::remove_extra_whitespace(&latex_decoder::render_latex(title, markup)))
+                .unwrap_or_default(),
+            venue: venue(paper),
+            volume: field(paper, "volume"),
+            number: field(paper, "number"),
+            pages: field(paper, "pages").replace("--", "\u{2013}").replace('-', "\u{2013}"),
+            doi: field(paper, "doi"),
+            url: paper["url"].as_str().unwrap_or("").to_string(),
+        }
+    }
+}
+
+fn initials(first: &str) -> String {
+    first.split_whitespace()
+        .map(|name| name.split('-')
+            .filter_map(|part| part.chars().next())
+            .map(|c| format!("{}.", c))
+            .collect::<Vec<_>>()
+            .join("-"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn join_names(names: &[String], separator: &str, last_separator: &str, pair_separator: &str) -> String {
+    match names.len() {
+        0 => String::new(),
+        1 => names[0].clone(),
+        2 => format!("{}{}{}", names[0], pair_separator, names[1]),
+        n => format!("{}{}{}", names[..n - 1].join(separator), last_separator, names[n - 1]),
+    }
+}
+
+/// Text escaped for HTML, both between tags and in attribute values.
+pub fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn escape(text: &str, markup: Markup) -> String {
+    match markup {
+        Markup::Html => escape_html(text),
+        _ => text.to_string(),
+    }
+}
+
+fn emph(text: &str, markup: Markup) -> String {
+    if text.is_empty() {
+        return String::new()
+    }
+    match markup {
+        Markup::Plain => text.to_string(),
+        Markup::Markdown => format!("*{}*", text),
+        Markup::Html => format!("<i>{}</i>", text),
+    }
+}
+
+fn link(url: &str, markup: Markup) -> String {
+    if url.is_empty() {
+        return String::new()
+    }
+    match markup {
+        Markup::Html => format!("<a href=\"{0}\">{0}</a>", escape(url, markup)),
+        _ => url.to_string(),
+    }
+}
+
+fn doi_or_url(reference: &Reference, markup: Markup) -> String {
+    if !reference.doi.is_empty() {
+        return link(&format!("https://doi.org/{}", reference.doi), markup)
+    }
+    return link(&reference.url, markup)
+}
+
+// Joins the non-empty parts, ending the result with a single period.
+fn sentence(parts: &[String], separator: &str) -> String {
+    let joined = parts.iter().filter(|part| !part.is_empty()).cloned().collect::<Vec<_>>().join(separator);
+    if joined.is_empty() || joined.ends_with('.') || joined.ends_with('?') || joined.ends_with('!') {
+        return joined
+    }
+    return format!("{}.", joined)
+}
+
+fn format_apa(r: &Reference, markup: Markup) -> String {
+    let names = r.authors.iter()
+        .map(|(first, last)| format!("{}, {}", last, initials(first)).trim_end_matches(", ").to_string())
+        .collect::<Vec<_>>();
+    let authors = escape(&join_names(&names, ", ", ", & ", ", & "), markup);
+    let year = if r.year.is_empty() { String::from("(n.d.)") } else { format!("({})", r.year) };
+    let mut venue = emph(&escape(&r.venue, markup), markup);
+    if !r.volume.is_empty() {
+        venue.push_str(&format!(", {}", emph(&r.volume, markup)));
+        if !r.number.is_empty() {
+            venue.push_str(&format!("({})", r.number));
+        }
+    }
+    if !r.pages.is_empty() {
+        venue.push_str(&format!(", {}", r.pages));
+    }
+    let venue = venue.trim_start_matches(", ").to_string();
+    let parts = [
+        sentence(&[authors], ""),
+        sentence(&[year], ""),
+        sentence(std::slice::from_ref(&r.title), ""),
+        sentence(&[venue], ""),
+        doi_or_url(r, markup),
+    ];
+    return parts.iter().filter(|part| !part.is_empty()).cloned().collect::<Vec<_>>().join(" ")
+}
+
+fn format_ieee(r: &Reference, markup: Markup) -> String {
+    let names = r.authors.iter()
+        .map(|(first, last)| format!("{} {}", initials(first), last).trim().to_string())
+        .collect::<Vec<_>>();
+    let authors = escape(&join_names(&names, ", ", ", and ", " and "), markup);
+    let mut parts = vec![
+        emph(&escape(&r.venue, markup), markup),
+    ];
+    if !r.volume.is_empty() {
+        parts.push(format!("vol. {}", r.volume));
+    }
+    if !r.number.is_empty() {
+        parts.push(format!("no. {}", r.number));
+    }
+    if !r.pages.is_empty() {
+        parts.push(format!("pp. {}", r.pages));
+    }
+    parts.push(r.year.clone());
+    if !r.doi.is_empty() {
+        parts.push(format!("doi: {}", escape(&r.doi, markup)));
+    }
+    let details = sentence(&parts, ", ");
+    let title = match (r.title.is_empty(), details.is_empty()) {
+        (true, _) => String::new(),
+        (false, true) => format!("\u{201C}{}.\u{201D}", r.title),
+        (false, false) => format!("\u{201C}{},\u{201D}", r.title),
+    };
+    let authors = if authors.is_empty() { authors } else { format!("{},", authors) };
+    return [authors, title, details].iter()
+        .filter(|part| !part.is_empty())
+        .cloned()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn format_acm(r: &Reference, markup: Markup) -> String {
+    let names = r.authors.iter()
+        .map(|(first, last)| format!("{} {}", first, last).trim().to_string())
+        .collect::<Vec<_>>();
+    let authors = escape(&join_names(&names, ", ", ", and ", " and "), markup);
+    let mut venue = emph(&escape(&r.venue, markup), markup);
+    if !r.volume.is_empty() {
+        venue.push_str(&format!(" {}", r.volume));
+        if !r.number.is_empty() {
+            venue.push_str(&format!(", {}", r.number));
+        }
+    }
+    if !r.year.is_empty() && !venue.is_empty() {
+        venue.push_str(&format!(" ({})", r.year));
+    }
+    if !r.pages.is_empty() {
+        venue.push_str(&format!(", {}", r.pages));
+    }
+    let venue = venue.trim_start_matches(", ").trim_start().to_string();
+    let parts = [
+        sentence(&[authors], ""),
+        sentence(std::slice::from_ref(&r.year), ""),
+        sentence(std::slice::from_ref(&r.title), ""),
+        sentence(&[venue], ""),
+        doi_or_url(r, markup),
+    ];
+    return parts.iter().filter(|part| !part.is_empty()).cloned().collect::<Vec<_>>().join(" ")
+}
+
+pub fn format_reference(paper: &json::JsonValue, style: Style, markup: Markup) -> String {
+    let reference = Reference::from_paper(paper, markup);
+    match style {
+        Style::Apa => format_apa(&reference, markup),
+        Style::Ieee => format_ieee(&reference, markup),
+        Style::Acm => format_acm(&reference, markup),
+    }
+}
+
+pub fn format_references(selection: &json::JsonValue, style: Style, markup: Markup) -> String {
+    let references = selection.entries()
+        .map(|(_, paper)| format_reference(paper, style, markup))
+        .collect::<Vec<_>>();
+    let numbered = style == Style::Ieee;
+    let mut result = String::new();
+    match markup {
+        Markup::Html => {
+            let list = if numbered { "ol" } else { "ul" };
+            result.push_str(&format!("<{}>\n", list));
+            for reference in references {
+                result.push_str(&format!("<li>{}</li>\n", reference));
+            }
+            result.push_str(&format!("</{}>\n", list));
+        },
+        _ => {
+            for (index, reference) in references.iter().enumerate() {
+                if numbered {
+                    result.push_str(&format!("[{}] ", index + 1));
+                }
+                result.push_str(reference);
+                result.push_str("\n\n");
+            }
+        },
+    }
+    return result
+}
+
+/// Format the selection with a CSL style file using pandoc's citeproc.
+pub fn format_with_csl(selection: json::JsonValue, csl: &Path, markup: Markup) -> String {
+    let bibliography = std::env::temp_dir().join(format!("paperman-{}.bib", std::process::id()));
+    std::fs::write(&bibliography, bibtex::generate_bibtex(selection, bibtex::Dialect::Bibtex))
+        .expect("Failed to write temporary bibliography");
+    let output_format = match markup {
+        Markup::Plain => "plain",
+        Markup::Markdown => "markdown_strict",
+        Markup::Html => "html",
+    };
+
+    let mut child = Command::new("pandoc")
+        .arg("--citeproc")
+        .arg("--bibliography").arg(&bibliography)
+        .arg("--csl").arg(csl)
+        .arg("-f").arg("markdown")
+        .arg("-t").arg(output_format)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn().expect("Failed to execute pandoc");
+    let stdin = child.stdin.as_mut().expect("Failed to get pandoc stdin");
+    stdin.write_all(b"---\nnocite: '@*'\n---\n").expect("Failed to write pandoc input");
+
+    let output = child.wait_with_output().expect("Failed to wait on pandoc");
+    let _ = std::fs::remove_file(&bibliography);
+    if !output.status.success() {
+        panic!("pandoc failed: {}", String::from_utf8_lossy(&output.stderr));
+    }
+    return String::from_utf8(output.stdout).unwrap()
+}
+
+#[cfg(test)]
+fn sample_paper() -> json::JsonValue {
+    json::object!{
+        "entry_type": "article",
+        "author": "Min Lyu and\n   Dong Su and\n   Ninghui Li",
+        "title": "Understanding the Sparse Vector Technique for Differential Privacy",
+        "journal": "Proc. {VLDB} Endow.",
+        "volume": "10",
+        "number": "6",
+        "pages": "637--648",
+        "year": "2017",
+        "doi": "10.14778/3055330.3055331",
+    }
+}
+
+#[test]
+fn format_apa_article() {
+    assert_eq!(
+        format_reference(&sample_paper(), Style::Apa, Markup::Plain),
+        "Lyu, M., Su, D., & Li, N. (2017). Understanding the Sparse Vector Technique for Differential Privacy. \
+         Proc. VLDB Endow., 10(6), 637\u{2013}648. https://doi.org/10.14778/3055330.3055331"
+    );
+}
+
+#[test]
+fn format_ieee_article() {
+    assert_eq!(
+        format_reference(&sample_paper(), Style::Ieee, Markup::Markdown),
+        "M. Lyu, D. Su, and N. Li, \u{201C}Understanding the Sparse Vector Technique for Differential Privacy,\u{201D} \
+         *Proc. VLDB Endow.*, vol. 10, no. 6, pp. 637\u{2013}648, 2017, doi: 10.14778/3055330.3055331."
+    );
+}
+
+#[test]
+fn format_acm_article() {
+    assert_eq!(
+        format_reference(&sample_paper(), Style::Acm, Markup::Html),
+        "Min Lyu, Dong Su, and Ninghui Li. 2017. Understanding the Sparse Vector Technique for Differential Privacy. \
+         <i>Proc. VLDB Endow.</i> 10, 6 (2017), 637\u{2013}648. \
+         <a href=\"https://doi.org/10.14778/3055330.3055331\">https://doi.org/10.14778/3055330.3055331</a>"
+    );
+}
+
+#[test]
+fn format_two_authors_and_last_first_names() {
+    let paper = json::object!{"author": "Räisä, Ossi and Honkela, Antti", "title": "T", "year": "2020"};
+    assert_eq!(format_reference(&paper, Style::Apa, Markup::Plain), "Räisä, O., & Honkela, A. (2020). T.");
+    assert_eq!(format_reference(&paper, Style::Ieee, Markup::Plain), "O. Räisä and A. Honkela, \u{201C}T,\u{201D} 2020.");
+}
+
+#[test]
+fn format_rich_titles() {
+    let paper = json::object!{"title": "\\emph{Sparse} $\\epsilon$-{DP} \\& More", "year": "2020"};
+    assert_eq!(
+        format_reference(&paper, Style::Apa, Markup::Html),
+        "(2020). <em>Sparse</em> <span class=\"math inline\">\\(\\epsilon\\)</span>-DP &amp; More."
+    );
+    assert_eq!(format_reference(&paper, Style::Apa, Markup::Markdown), "(2020). *Sparse* $\\epsilon$-DP & More.");
+    assert_eq!(format_reference(&paper, Style::Apa, Markup::Plain), "(2020). Sparse ϵ-DP & More.");
+}
+
+#[test]
+fn format_numbered_ieee_list() {
+    let mut selection = json::object!{};
+    selection["a"] = json::object!{"title": "A"};
+    selection["b"] = json::object!{"title": "B"};
+    let result = format_references(&selection, Style::Ieee, Markup::Plain);
+    assert_eq!(result, "[1] \u{201C}A.\u{201D}\n\n[2] \u{201C}B.\u{201D}\n\n");
+}
+
+#[test]
+fn escape_quotes_in_links() {
+    let paper = json::object!{"title": "T", "year": "2020", "url": "https://example.org/?q=\"x\"&y=<z>"};
+    assert_eq!(
+        format_reference(&paper, Style::Apa, Markup::Html),
+        "(2020). T. <a href=\"https://example.org/?q=&quot;x&quot;&amp;y=&lt;z&gt;\">https://example.org/?q=&quot;x&quot;&amp;y=&lt;z&gt;</a>"
+    );
+}
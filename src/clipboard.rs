@@ -0,0 +1,28 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+// Clipboard programs tried in order, with their arguments.
+const CLIPBOARDS: [(&str, &[&str]); 4] = [
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("xsel", &["--clipboard", "--input"]),
+    ("pbcopy", &[]),
+];
+
+/// Copy text to the system clipboard with the first available clipboard program.
+pub fn copy(text: &str) {
+    for (program, args) in CLIPBOARDS.iter() {
+        let child = Command::new(program)
+            .args(args.iter())
+            .stdin(Stdio::piped())
+            .spawn();
+        if let Ok(mut child) = child {
+            let stdin = child.stdin.as_mut().expect("Failed to get clipboard stdin");
+            stdin.write_all(text.as_bytes()).expect("Failed to write to clipboard");
+            drop(child.stdin.take());
+            child.wait().expect("Failed to wait on clipboard");
+            return
+        }
+    }
+    panic!("No clipboard program found, install wl-copy, xclip or xsel");
+}
@@ -1,6 +1,13 @@
 use structopt::StructOpt;
+use structopt::clap::AppSettings;
 use std::path::PathBuf;
+use crate::bibtex::Dialect;
+use crate::citation::Style;
+use crate::site::GroupBy;
+use crate::template::Template;
+use crate::venues::VenueStyle;
 
+#[allow(dead_code)]
 #[derive(Debug, StructOpt)]
 pub struct Add {
     #[structopt(parse(from_os_str))]
@@ -11,6 +18,7 @@ pub struct Add {
 }
 
 #[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::TrailingVarArg)]
 pub struct BibtexFileCmd {
     #[structopt(parse(from_os_str))]
     pub bibtex: PathBuf,
@@ -18,13 +26,94 @@ pub struct BibtexFileCmd {
 }
 
 #[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::TrailingVarArg)]
 pub struct BibtexInputCmd {
     pub remaining_args: Vec<String>
 }
 
+#[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::TrailingVarArg)]
+pub struct RisFileCmd {
+    #[structopt(parse(from_os_str))]
+    pub ris: PathBuf,
+    pub remaining_args: Vec<String>,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::TrailingVarArg)]
+pub struct RisInputCmd {
+    pub remaining_args: Vec<String>
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ExportCmd {
+    /// Output format
+    #[structopt(long, default_value = "bibtex", possible_values = &["bibtex", "ris"])]
+    pub format: String,
+
+    /// BibTeX dialect used for field names and entry types
+    #[structopt(long, default_value = "bibtex", possible_values = &["bibtex", "biblatex"])]
+    pub dialect: Dialect,
+
+    /// Copy to the clipboard instead of printing
+    #[structopt(long)]
+    pub clipboard: bool,
+
+    /// Write known journals and conferences by their full name or abbreviation
+    #[structopt(long, possible_values = &["full", "abbrev"])]
+    pub venue: Option<VenueStyle>,
+
+    /// Write accented letters and other non-ASCII characters as LaTeX
+    #[structopt(long)]
+    pub ascii: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct FormatCmd {
+    /// Citation style
+    #[structopt(long, default_value = "apa", possible_values = &["apa", "ieee", "acm"])]
+    pub style: Style,
+
+    /// CSL style file, formatted with pandoc instead of the built-in styles
+    #[structopt(long, parse(from_os_str))]
+    pub csl: Option<PathBuf>,
+
+    /// Output Markdown
+    #[structopt(long)]
+    pub markdown: bool,
+
+    /// Output HTML
+    #[structopt(long, conflicts_with = "markdown")]
+    pub html: bool,
+}
+
 #[derive(Debug, StructOpt)]
 pub struct ListCmd {
-    pub field: String
+    /// Field to print, decoded from LaTeX
+    pub field: Option<String>,
+
+    /// Template such as '{key}\t{year}\t{author|first}: {title}', or one of
+    /// the presets short, long and tsv. Filters: first, lastnames, clean,
+    /// upper, lower, html, markdown and default:<value>
+    #[structopt(long, conflicts_with = "field")]
+    pub format: Option<Template>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct TagCmd {
+    pub tag: String,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct MarkCmd {
+    #[structopt(possible_values = &["unread", "reading", "read", "skimmed"])]
+    pub status: String,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct RateCmd {
+    #[structopt(possible_values = &["1", "2", "3", "4", "5"])]
+    pub rating: String,
 }
 
 #[derive(Debug, StructOpt)]
@@ -32,6 +121,7 @@ pub struct UpdateCmd {
     pub field: String,
     pub value: String
 }
+#[allow(dead_code)]
 #[derive(Debug, StructOpt)]
 pub struct DoiCmd {
     pub doi: String,
@@ -39,11 +129,174 @@ pub struct DoiCmd {
 }
 
 #[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::TrailingVarArg)]
 pub struct PickCmd {
+    /// Menu to pick with. Defaults to the "picker" config value, or the
+    /// first available of rofi, fzf, dmenu and a terminal prompt
+    #[structopt(long, possible_values = &["auto", "rofi", "fzf", "dmenu", "tty"])]
+    pub picker: Option<String>,
+
+    /// Template for the menu lines, as in list. Defaults to the
+    /// "pick_format" config value
+    #[structopt(long)]
+    pub format: Option<Template>,
+
+    pub remaining_args: Vec<String>,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::TrailingVarArg)]
+pub struct SortCmd {
+    /// Field to sort by, or "key" for the citation key
+    pub field: String,
+
+    /// Sort in descending order
+    #[structopt(long)]
+    pub desc: bool,
+
+    pub remaining_args: Vec<String>,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::TrailingVarArg)]
+pub struct LimitCmd {
+    /// Number of papers to keep
+    pub count: usize,
+    pub remaining_args: Vec<String>,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::TrailingVarArg)]
+pub struct RecentCmd {
+    /// Number of papers to keep
+    pub count: usize,
+
+    /// Timestamp to order by
+    #[structopt(long, default_value = "added", possible_values = &["added", "modified", "opened"])]
+    pub by: String,
+
+    pub remaining_args: Vec<String>,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::TrailingVarArg)]
+pub struct AddedSinceCmd {
+    /// Date in the form YYYY-MM-DD
+    #[structopt(parse(try_from_str = crate::timestamps::parse_date))]
+    pub date: String,
+    pub remaining_args: Vec<String>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct LogCmd {
+    /// Number of operations to show
+    #[structopt(short = "n", long, default_value = "10")]
+    pub count: usize,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct UndoCmd {
+    /// Number of operations to undo
+    #[structopt(default_value = "1")]
+    pub count: usize,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct SyncCmd {
+    /// Remote name or path of the repository to sync with
+    pub remote: Option<String>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct MigrateCmd {
+    /// Storage to copy the library to
+    #[structopt(possible_values = &["json", "directory", "sqlite"])]
+    pub storage: String,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct LintCmd {
+    /// Rules to check against: bibtex or biblatex
+    #[structopt(long, default_value = "bibtex")]
+    pub dialect: Dialect,
+
+    /// Apply the safe fixes to papers in the library
+    #[structopt(long)]
+    pub fix: bool,
+
+    /// Also report fields not standard for the entry type
+    #[structopt(long)]
+    pub strict: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct SiteCmd {
+    /// Directory to write index.html, style.css and the PDFs to
+    #[structopt(long, parse(from_os_str))]
+    pub out: PathBuf,
+
+    /// Group papers by year or entry type
+    #[structopt(long, default_value = "year", possible_values = &["year", "type"])]
+    pub group_by: GroupBy,
+
+    /// Citation style of the entries
+    #[structopt(long, default_value = "apa", possible_values = &["apa", "ieee", "acm"])]
+    pub style: Style,
+
+    /// Heading and title of the page
+    #[structopt(long, default_value = "Publications")]
+    pub title: String,
+
+    /// Directory with page.html, group.html, entry.html or style.css to use
+    /// instead of the built-in ones
+    #[structopt(long, parse(from_os_str))]
+    pub templates: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct StatsCmd {
+    /// Fields to count values of, such as year, venue, author or tags
+    pub fields: Vec<String>,
+
+    /// Number of most common values shown for fields other than numbers
+    #[structopt(long, default_value = "10")]
+    pub top: usize,
+
+    /// Output JSON instead of a table
+    #[structopt(long)]
+    pub json: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct IndexCmd {
+    /// Re-extract text even for PDFs that have not changed
+    #[structopt(long)]
+    pub force: bool,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::TrailingVarArg)]
+pub struct SearchCmd {
+    /// Words to search for in the indexed PDFs
+    pub query: String,
+    pub remaining_args: Vec<String>,
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::TrailingVarArg)]
+pub struct FindCmd {
+    /// Words to look for in title, authors, venue, tags, the note field and the Markdown notes
+    pub query: String,
+
+    /// Maximum number of results
+    #[structopt(long, short = "n", default_value = "10")]
+    pub limit: usize,
+
     pub remaining_args: Vec<String>,
 }
 
 #[derive(Debug, StructOpt)]
+#[structopt(setting = AppSettings::TrailingVarArg)]
 pub struct ByCmd {
     pub field: String,
     pub value: String,
@@ -64,32 +317,106 @@ pub enum Command{
     /// Select all entries from BibTeX from stdin
     Bibtex(BibtexInputCmd),
 
-    /// Print selection as BibTeX to stdout
-    Export,
+    /// Select all entries from RIS file
+    RisFile(RisFileCmd),
+
+    /// Select all entries from RIS from stdin
+    Ris(RisInputCmd),
+
+    /// Print selection as BibTeX, BibLaTeX or RIS to stdout
+    Export(ExportCmd),
 
     /// Print selection as json
     Print,
 
-    /// Print value of given field for selection 
+    /// Print selection as formatted references
+    Format(FormatCmd),
+
+    /// Print value of given field or template for selection
     List(ListCmd),
 
     /// Open selected papers
     Open,
 
+    /// Copy citation keys of selected papers to the clipboard
+    CopyKey,
+
     /// Pick one or more selected papers from a menu
     Pick(PickCmd),
 
     /// Filter selected paper by given field and value
     By(ByCmd),
 
-    // AddTag,
+    /// Rank selected papers by fuzzy matching of their metadata and notes.
+    /// Prints the results when no command follows
+    Find(FindCmd),
+
+    /// Extract and index the text of attached PDFs of selected papers
+    Index(IndexCmd),
+
+    /// Rank selected papers by the text of their PDFs. Prints snippets when
+    /// no command follows
+    Search(SearchCmd),
+
+    /// Sort selected papers by given field
+    Sort(SortCmd),
+
+    /// Keep the first N selected papers
+    Head(LimitCmd),
 
-    // RemoveTag,
+    /// Keep the last N selected papers
+    Tail(LimitCmd),
+
+    /// Keep the N most recently added, modified or opened papers
+    Recent(RecentCmd),
+
+    /// Keep papers added on or after the given date
+    AddedSince(AddedSinceCmd),
+
+    /// Add a tag to selected papers
+    AddTag(TagCmd),
+
+    /// Remove a tag from selected papers
+    RemoveTag(TagCmd),
 
     /// Update the value of a field for selected papers
     Update(UpdateCmd),
 
-    // Edit,
+    /// Set the reading status of selected papers
+    Mark(MarkCmd),
+
+    /// Rate selected papers from 1 to 5
+    Rate(RateCmd),
+
+    /// Edit the Markdown notes of selected papers in $EDITOR
+    Note,
+
+    /// Edit selected papers as BibTeX in $EDITOR
+    Edit,
+
+    /// Browse selected papers in a full-screen terminal interface
+    Tui,
+
+    /// Show the most recent changes to the library
+    Log(LogCmd),
+
+    /// Revert the most recent changes to the library
+    Undo(UndoCmd),
+
+    /// Pull and push the git-versioned library
+    Sync(SyncCmd),
+
+    /// Copy the library to another storage
+    Migrate(MigrateCmd),
+
+    /// Check the selected papers for missing fields and malformed values
+    Lint(LintCmd),
+
+    /// Write the selected papers to a static HTML publication list
+    Site(SiteCmd),
+
+    /// Count the selected papers by year, venue, author, tag and other fields
+    Stats(StatsCmd),
 }
 
 #[derive(Debug, StructOpt)]
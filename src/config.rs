@@ -0,0 +1,23 @@
+use std::path::Path;
+
+// The config file is a JSON object beside the database, for example
+//
+//   {
+//     "picker": "rofi",
+//     "pick_format": "{title|clean} | {author|lastnames|clean} | {year} | {journal|clean}",
+//     "pick_keybindings": { "Alt+o": "open", "Alt+e": "export --format ris" },
+//     "storage": "directory",
+//     "entry_format": "bib",
+//     "git": true,
+//     "git_remote": "/mnt/shared/library.git"
+//   }
+
+pub fn load_config(path: &Path) -> json::JsonValue {
+    match std::fs::read_to_string(path) {
+        Ok(config) => json::parse(&config).expect("Failed to parse config file"),
+        Err(error) => match error.kind() {
+            std::io::ErrorKind::NotFound => json::object!{},
+            _ => panic!("Could not open config file: {:?}", error),
+        }
+    }
+}
@@ -1,25 +1,204 @@
+use crate::ranking;
 use crate::string_cleaner;
+use std::collections::HashMap;
 
-pub fn match_values(value: &json::JsonValue, field_value: &json::JsonValue, clean: bool) -> bool {
-    match value {
-        json::JsonValue::String(str_val) => {
+// Fields searched by `find`, along with the Markdown note of the paper.
+const FIND_FIELDS: [&str; 9] = [
+    "title", "author", "editor", "journal", "booktitle", "publisher", "tags", "keywords", "note"
+];
+
+// Fields with a fixed set of values, which only match the whole value, so
+// `by status read` leaves out unread papers.
+pub const EXACT_FIELDS: [&str; 2] = ["status", "rating"];
+
+/// How `by` compares a value to the field of a paper.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Matching {
+    Exact,
+    Contains,
+    /// Contains, after decoding LaTeX and ignoring case.
+    Cleaned,
+}
+
+pub fn match_values(value: &json::JsonValue, field_value: &json::JsonValue, matching: Matching) -> bool {
+    match value.as_str() {
+        Some(str_val) => {
             match field_value {
-                json::JsonValue::String(field_str) => match_string(str_val, &field_str, clean),
                 json::JsonValue::Array(field_arr) => field_arr
-                    .iter().any(|s| match_string(s.as_str().unwrap(), str_val, clean)),
-                _ => false
+                    .iter().any(|s| match_string(str_val, &s.to_string(), matching)),
+                json::JsonValue::Null => false,
+                field_value => match_string(str_val, &field_value.to_string(), matching),
             }
         },
-        _ => false,
+        None => false,
+    }
+}
+
+fn match_string(needle: &str, haystack: &str, matching: Matching) -> bool {
+    match matching {
+        Matching::Exact => return haystack == needle,
+        Matching::Contains => return haystack.contains(needle),
+        Matching::Cleaned => {
+            let cleaned_haystack = string_cleaner::clean_and_decode(haystack).to_lowercase();
+            let cleaned_needle = string_cleaner::clean_and_decode(needle).to_lowercase();
+            return cleaned_haystack.contains(cleaned_needle.as_str())
+        },
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + if a_char == *b_char { 0 } else { 1 };
+            current.push(usize::min(substitution, usize::min(previous[j + 1], current[j]) + 1));
+        }
+        previous = current;
+    }
+    return previous[b.len()]
+}
+
+/// How well a query word matches a word of a paper, between 0 and 1.
+fn word_similarity(query: &str, word: &str) -> f64 {
+    if query == word {
+        return 1.0
+    }
+    let length = query.chars().count();
+    if length >= 3 && word.starts_with(query) {
+        return 0.9
     }
+    let allowed = match length {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    };
+    let distance = edit_distance(query, word);
+    if distance <= allowed {
+        return 0.8 - 0.2 * distance as f64
+    }
+    return 0.0
+}
+
+fn paper_words(paper: &json::JsonValue, note: Option<&String>) -> Vec<String> {
+    let mut words = note.map_or_else(Vec::new, |note| string_cleaner::tokenize(&string_cleaner::clean_and_decode(note)));
+    for field in FIND_FIELDS.iter() {
+        let values = match &paper[*field] {
+            json::JsonValue::Array(values) => values.iter().map(|value| value.to_string()).collect(),
+            json::JsonValue::Null => Vec::new(),
+            value => vec![value.to_string()],
+        };
+        for value in values {
+            words.extend(string_cleaner::tokenize(&string_cleaner::clean_and_decode(&value)));
+        }
+    }
+    return words
+}
+
+/// Rank the papers of the selection, with their notes by key, by fuzzy BM25
+/// score against the query words, returning at most `limit` matching papers.
+pub fn rank_fuzzy(selection: &json::JsonValue, notes: &HashMap<String, String>, query: &str, limit: usize) -> Vec<(String, f64)> {
+    let terms = string_cleaner::tokenize(&string_cleaner::clean_and_decode(query));
+    let papers = selection.entries()
+        .map(|(key, paper)| (key.to_string(), paper_words(paper, notes.get(key))))
+        .collect::<Vec<_>>();
+    let total_docs = papers.len();
+    let average_length = papers.iter().map(|(_, words)| words.len()).sum::<usize>() as f64 / usize::max(total_docs, 1) as f64;
+
+    // Fuzzy term frequency of each query term in each paper.
+    let frequencies = papers.iter()
+        .map(|(_, words)| terms.iter()
+            .map(|term| words.iter().map(|word| word_similarity(term, word)).sum::<f64>())
+            .collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    let doc_counts = (0..terms.len())
+        .map(|term| frequencies.iter().filter(|tfs| tfs[term] > 0.0).count())
+        .collect::<Vec<_>>();
+
+    let mut results = papers.iter().zip(frequencies.iter())
+        .map(|((key, words), tfs)| {
+            let score = tfs.iter().zip(doc_counts.iter())
+                .map(|(tf, doc_count)| ranking::bm25(*tf, words.len(), average_length, *doc_count, total_docs))
+                .sum::<f64>();
+            (key.clone(), score)
+        })
+        .filter(|(_, score)| *score > 0.0)
+        .collect::<Vec<_>>();
+    results.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    results.truncate(limit);
+    return results
+}
+
+#[test]
+fn match_short_and_array_values() {
+    assert!(match_values(&json::from("read"), &json::from("read"), Matching::Exact));
+    assert!(!match_values(&json::from("read"), &json::from("unread"), Matching::Exact));
+    assert!(match_values(&json::from("2017"), &json::from(2017), Matching::Contains));
+    assert!(match_values(&json::from("dp"), &json::array!["ml", "dp"], Matching::Contains));
+    assert!(!match_values(&json::from("dp"), &json::JsonValue::Null, Matching::Contains));
+    let unread = json::object!{"title": "T"};
+    let status = crate::reading::field(&unread, "status");
+    assert!(!match_values(&json::from("read"), &status, Matching::Exact));
 }
 
-fn match_string(needle: &str, haystack: &str, clean: bool) -> bool {
-    if clean {
-        let cleaned_haystack = string_cleaner::clean_and_decode(haystack).to_lowercase();
-        let cleaned_needle = string_cleaner::clean_and_decode(needle).to_lowercase();
-        return cleaned_haystack.contains(cleaned_needle.as_str())
-    } else {
-        return haystack.contains(needle)
+#[cfg(test)]
+fn sample_selection() -> json::JsonValue {
+    json::object!{
+        "svt": {
+            "title": "Understanding the Sparse Vector Technique for Differential Privacy",
+            "author": "Min Lyu and Dong Su and Ninghui Li",
+            "journal": "Proc. {VLDB} Endow.",
+        },
+        "dpsgd": {
+            "title": "Deep Learning with Differential Privacy",
+            "author": "Mart{\\'\\i}n Abadi and others",
+            "tags": ["dp", "deep learning"],
+        },
+        "vi": {
+            "title": "Variational Inference: A Review for Statisticians",
+            "author": "David M. Blei",
+        },
     }
 }
+
+#[test]
+fn find_word_order_independent() {
+    let results = rank_fuzzy(&sample_selection(), &HashMap::new(), "sparse vector differential", 10);
+    assert_eq!(results[0].0, "svt");
+    assert_eq!(results.len(), 2);
+}
+
+#[test]
+fn find_with_typos_and_accents() {
+    let results = rank_fuzzy(&sample_selection(), &HashMap::new(), "variatonal infernce", 10);
+    assert_eq!(results[0].0, "vi");
+    let results = rank_fuzzy(&sample_selection(), &HashMap::new(), "martin abadi", 10);
+    assert_eq!(results[0].0, "dpsgd");
+}
+
+#[test]
+fn find_in_tags_and_venue() {
+    assert_eq!(rank_fuzzy(&sample_selection(), &HashMap::new(), "vldb", 10)[0].0, "svt");
+    assert_eq!(rank_fuzzy(&sample_selection(), &HashMap::new(), "deep", 10)[0].0, "dpsgd");
+}
+
+#[test]
+fn find_in_notes() {
+    let notes = HashMap::from([(String::from("vi"), String::from("# Review\n\nCompare with Monte Carlo methods.\n"))]);
+    assert_eq!(rank_fuzzy(&sample_selection(), &notes, "monte carlo", 10)[0].0, "vi");
+    assert!(rank_fuzzy(&sample_selection(), &HashMap::new(), "monte carlo", 10).is_empty());
+}
+
+#[test]
+fn find_limits_results() {
+    assert_eq!(rank_fuzzy(&sample_selection(), &HashMap::new(), "differential privacy", 1).len(), 1);
+    assert!(rank_fuzzy(&sample_selection(), &HashMap::new(), "quantum", 10).is_empty());
+}
+
+#[test]
+fn edit_distances() {
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("same", "same"), 0);
+}
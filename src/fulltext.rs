@@ -0,0 +1,239 @@
+use crate::ranking;
+use crate::string_cleaner;
+use std::path::Path;
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+
+// The full-text index is a JSON file of the form
+//
+//   {
+//     "papers": { key: { "file": pdf path, "modified": seconds, "text": text file, "length": words } },
+//     "terms": { term: { key: count } }
+//   }
+//
+// with the extracted text of each paper stored in the text directory.
+
+const SNIPPET_WORDS: usize = 12;
+
+pub fn load_index(path: &Path) -> json::JsonValue {
+    match std::fs::read_to_string(path) {
+        Ok(index) => json::parse(&index).expect("Failed to parse full-text index"),
+        Err(error) => match error.kind() {
+            std::io::ErrorKind::NotFound => json::object!{"papers": {}, "terms": {}},
+            _ => panic!("Could not open full-text index: {:?}", error),
+        }
+    }
+}
+
+pub fn save_index(path: &Path, index: &json::JsonValue) {
+    std::fs::write(path, index.dump()).expect("Could not save full-text index");
+}
+
+fn modified_seconds(file: &Path) -> Option<u64> {
+    let modified = std::fs::metadata(file).ok()?.modified().ok()?;
+    return modified.duration_since(UNIX_EPOCH).ok().map(|duration| duration.as_secs())
+}
+
+/// Name of the text file of a paper. A paper keeps the name recorded in the
+/// index, and a new one gets a suffix such as `_2` when another paper
+/// already has its name, as keys like `a:b` and `a/b` share a file stem.
+fn text_file_name(index: &json::JsonValue, key: &str) -> String {
+    if let Some(text_file) = index["papers"][key]["text"].as_str() {
+        return text_file.to_string()
+    }
+    let taken = index["papers"].entries()
+        .filter_map(|(_, entry)| entry["text"].as_str())
+        .collect::<std::collections::HashSet<_>>();
+    let stem = string_cleaner::key_file_stem(key);
+    return std::iter::once(format!("{}.txt", stem))
+        .chain((2..).map(|suffix| format!("{}_{}.txt", stem, suffix)))
+        .find(|text_file| !taken.contains(text_file.as_str()))
+        .expect("No text file name left")
+}
+
+/// Extract the text of a PDF with pdftotext.
+pub fn extract_text(pdf: &Path) -> Result<String, String> {
+    if !pdf.exists() {
+        return Err(format!("{:?} does not exist", pdf))
+    }
+    let output = Command::new("pdftotext")
+        .arg("-enc").arg("UTF-8")
+        .arg(pdf)
+        .arg("-")
+        .output()
+        .map_err(|error| format!("could not run pdftotext: {}", error))?;
+    if !output.status.success() {
+        return Err(format!("pdftotext failed on {:?}: {}", pdf, String::from_utf8_lossy(&output.stderr).trim()))
+    }
+    return Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn remove_paper(index: &mut json::JsonValue, key: &str) {
+    index["papers"].remove(key);
+    let mut empty_terms = Vec::new();
+    for (term, counts) in index["terms"].entries_mut() {
+        counts.remove(key);
+        if counts.is_empty() {
+            empty_terms.push(term.to_string());
+        }
+    }
+    for term in empty_terms {
+        index["terms"].remove(&term);
+    }
+}
+
+/// Add the text of a paper to the index, replacing any earlier version.
+pub fn add_text(index: &mut json::JsonValue, key: &str, text: &str) {
+    remove_paper(index, key);
+    let tokens = string_cleaner::tokenize(text);
+    for token in &tokens {
+        let count = index["terms"][token.as_str()][key].as_usize().unwrap_or(0);
+        index["terms"][token.as_str()][key] = json::from(count + 1);
+    }
+    index["papers"][key]["length"] = json::from(tokens.len());
+}
+
+/// Index the attached PDF of a paper unless it is already up to date.
+/// Returns whether the paper was (re)indexed, or why its text could not be
+/// extracted.
+pub fn index_paper(index: &mut json::JsonValue, text_dir: &Path, key: &str, pdf: &Path, force: bool) -> Result<bool, String> {
+    let modified = modified_seconds(pdf);
+    let entry = &index["papers"][key];
+    let up_to_date = entry["file"].as_str() == pdf.to_str()
+        && entry["modified"].as_u64() == modified;
+    if up_to_date && !force {
+        return Ok(false)
+    }
+
+    let text = extract_text(pdf)?;
+    std::fs::create_dir_all(text_dir).expect("Could not create full-text directory");
+    let text_file = text_file_name(index, key);
+    std::fs::write(text_dir.join(&text_file), &text).expect("Could not save extracted text");
+
+    add_text(index, key, &text);
+    let entry = &mut index["papers"][key];
+    entry["file"] = json::from(pdf.to_str().unwrap_or(""));
+    entry["modified"] = json::from(modified);
+    entry["text"] = json::from(text_file);
+    return Ok(true)
+}
+
+/// Rank the papers of the selection by BM25 score against the query,
+/// dropping papers that match none of its terms.
+pub fn rank(index: &json::JsonValue, selection: &json::JsonValue, query: &str) -> Vec<(String, f64)> {
+    let terms = string_cleaner::tokenize(query);
+    let papers = &index["papers"];
+    let total_docs = papers.len();
+    let total_length = papers.entries().map(|(_, paper)| paper["length"].as_usize().unwrap_or(0)).sum::<usize>();
+    let average_length = if total_docs > 0 { total_length as f64 / total_docs as f64 } else { 0.0 };
+
+    let mut results = selection.entries()
+        .filter(|(key, _)| papers.has_key(key))
+        .map(|(key, _)| {
+            let length = papers[key]["length"].as_usize().unwrap_or(0);
+            let score = terms.iter().map(|term| {
+                let counts = &index["terms"][term.as_str()];
+                let count = counts[key].as_usize().unwrap_or(0);
+                ranking::bm25(count as f64, length, average_length, counts.len(), total_docs)
+            }).sum::<f64>();
+            (key.to_string(), score)
+        })
+        .filter(|(_, score)| *score > 0.0)
+        .collect::<Vec<_>>();
+    results.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    return results
+}
+
+/// Load the extracted text of an indexed paper.
+pub fn load_text(index: &json::JsonValue, text_dir: &Path, key: &str) -> Option<String> {
+    let text_file = index["papers"][key]["text"].as_str()?;
+    return std::fs::read_to_string(text_dir.join(text_file)).ok()
+}
+
+/// A few words of context around the first occurrence of a query term.
+pub fn snippet(text: &str, query: &str) -> String {
+    let terms = string_cleaner::tokenize(query);
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    let position = words.iter()
+        .position(|word| string_cleaner::tokenize(word).iter().any(|token| terms.contains(token)))
+        .unwrap_or(0);
+    let start = position.saturating_sub(SNIPPET_WORDS / 2);
+    let end = usize::min(start + SNIPPET_WORDS, words.len());
+    let mut result = words[start..end].join(" ");
+    if start > 0 {
+        result = format!("...{}", result);
+    }
+    if end < words.len() {
+        result.push_str("...");
+    }
+    return result
+}
+
+#[cfg(test)]
+fn sample_index() -> json::JsonValue {
+    let mut index = json::object!{"papers": {}, "terms": {}};
+    add_text(&mut index, "svt", "The sparse vector technique answers threshold queries with differential privacy.");
+    add_text(&mut index, "dpsgd", "Differentially private stochastic gradient descent clips gradients and adds noise.");
+    add_text(&mut index, "other", "A paper about Bayesian inference.");
+    index
+}
+
+#[cfg(test)]
+fn sample_selection() -> json::JsonValue {
+    json::object!{"svt": {}, "dpsgd": {}, "other": {}}
+}
+
+#[test]
+fn rank_matching_papers() {
+    let results = rank(&sample_index(), &sample_selection(), "sparse vector privacy");
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].0, "svt");
+}
+
+#[test]
+fn rank_orders_by_score() {
+    let results = rank(&sample_index(), &sample_selection(), "gradient noise inference");
+    let keys = results.iter().map(|(key, _)| key.as_str()).collect::<Vec<_>>();
+    assert_eq!(keys, vec!["dpsgd", "other"]);
+}
+
+#[test]
+fn rank_only_within_selection() {
+    let results = rank(&sample_index(), &json::object!{"other": {}}, "sparse");
+    assert!(results.is_empty());
+}
+
+#[test]
+fn reindexing_replaces_terms() {
+    let mut index = sample_index();
+    add_text(&mut index, "svt", "Nothing here.");
+    assert!(index["terms"]["sparse"].is_null());
+    assert_eq!(index["papers"]["svt"]["length"], 2);
+}
+
+#[test]
+fn distinct_text_files_for_similar_keys() {
+    let mut index = json::object!{"papers": {}, "terms": {}};
+    index["papers"]["a:b"]["text"] = json::from(text_file_name(&index, "a:b"));
+    index["papers"]["a/b"]["text"] = json::from(text_file_name(&index, "a/b"));
+    assert_eq!(index["papers"]["a:b"]["text"], "a_b.txt");
+    assert_eq!(index["papers"]["a/b"]["text"], "a_b_2.txt");
+    assert_eq!(text_file_name(&index, "a:b"), "a_b.txt");
+}
+
+#[test]
+fn snippet_around_match() {
+    let text = "one two three four five six seven eight nine ten eleven twelve Sparse thirteen fourteen";
+    assert_eq!(
+        snippet(text, "sparse"),
+        "...seven eight nine ten eleven twelve Sparse thirteen fourteen"
+    );
+}
+
+#[test]
+fn skip_missing_pdf() {
+    let mut index = sample_index();
+    let text_dir = std::env::temp_dir().join("paperman-missing-pdf");
+    assert!(index_paper(&mut index, &text_dir, "missing", Path::new("/nonexistent/missing.pdf"), false).is_err());
+    assert!(index["papers"]["missing"].is_null());
+}
@@ -0,0 +1,235 @@
+use std::collections::HashSet;
+use std::io::Write;
+use std::path::Path;
+
+// The journal is an append-only file with one JSON operation per line:
+//
+//   { "id": 3, "time": timestamp, "command": "update title foo",
+//     "changes": [ { "key": key, "before": fields, "after": fields } ],
+//     "undoes": [ ids ] }
+//
+// An added paper has a null "before" and a removed paper a null "after".
+// For a changed paper only the fields that differ are stored, and a field
+// missing on one side did not exist there. "undoes" is only present on the
+// operations written by undo.
+
+/// The changes turning the `before` database into the `after` one.
+pub fn diff(before: &json::JsonValue, after: &json::JsonValue) -> Vec<json::JsonValue> {
+    let mut changes = Vec::new();
+    for (key, paper) in before.entries() {
+        if !after.has_key(key) {
+            changes.push(json::object!{"key": key, "before": paper.clone(), "after": null});
+        }
+    }
+    for (key, paper) in after.entries() {
+        if !before.has_key(key) {
+            changes.push(json::object!{"key": key, "before": null, "after": paper.clone()});
+            continue
+        }
+        let old = &before[key];
+        let mut old_fields = json::object!{};
+        let mut new_fields = json::object!{};
+        for (field, value) in old.entries() {
+            if paper[field] != *value {
+                old_fields[field] = value.clone();
+            }
+        }
+        for (field, value) in paper.entries() {
+            if old[field] != *value {
+                new_fields[field] = value.clone();
+            }
+        }
+        if !old_fields.is_empty() || !new_fields.is_empty() {
+            changes.push(json::object!{"key": key, "before": old_fields, "after": new_fields});
+        }
+    }
+    return changes
+}
+
+pub fn load(path: &Path) -> Vec<json::JsonValue> {
+    match std::fs::read_to_string(path) {
+        Ok(journal) => journal.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| json::parse(line).expect("Failed to parse journal"))
+            .collect(),
+        Err(error) => match error.kind() {
+            std::io::ErrorKind::NotFound => Vec::new(),
+            _ => panic!("Could not open journal: {:?}", error),
+        }
+    }
+}
+
+/// Whether a change only touches the timestamps kept up to date by paperman,
+/// like opening a paper does.
+fn only_timestamps(change: &json::JsonValue) -> bool {
+    let timestamps = [crate::timestamps::LAST_OPENED, crate::timestamps::MODIFIED];
+    return !change["before"].is_null() && !change["after"].is_null()
+        && change["before"].entries().chain(change["after"].entries())
+            .all(|(field, _)| timestamps.contains(&field))
+}
+
+/// Append the changes between two versions of the database, if any, and
+/// return the operation recorded. Operations that only update timestamps
+/// are not recorded, so that undo reverts the last real edit.
+pub fn record(path: &Path, before: &json::JsonValue, after: &json::JsonValue, command: &str, undoes: &[usize]) -> Option<json::JsonValue> {
+    let changes = diff(before, after);
+    if changes.iter().all(only_timestamps) {
+        return None
+    }
+    let mut operation = json::object!{
+        "id": load(path).last().and_then(|last| last["id"].as_usize()).map_or(0, |id| id + 1),
+        "time": crate::timestamps::now(),
+        "command": command,
+        "changes": changes,
+    };
+    if !undoes.is_empty() {
+        operation["undoes"] = json::from(undoes.to_vec());
+    }
+    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(path)
+        .expect("Could not open journal");
+    writeln!(file, "{}", operation.dump()).expect("Could not write journal");
+    return Some(operation)
+}
+
+/// The most recent operations that can still be undone, newest first.
+/// Undo operations themselves and operations already undone are skipped.
+pub fn undoable(journal: &[json::JsonValue], count: usize) -> Vec<&json::JsonValue> {
+    let undone = journal.iter()
+        .flat_map(|operation| operation["undoes"].members())
+        .filter_map(|id| id.as_usize())
+        .collect::<HashSet<_>>();
+    return journal.iter().rev()
+        .filter(|operation| operation["undoes"].is_null())
+        .filter(|operation| !operation["id"].as_usize().is_some_and(|id| undone.contains(&id)))
+        .take(count)
+        .collect()
+}
+
+/// Revert the changes of an operation in the database.
+pub fn revert(db: &mut json::JsonValue, operation: &json::JsonValue) {
+    for change in operation["changes"].members() {
+        let key = change["key"].as_str().unwrap_or("");
+        let (before, after) = (&change["before"], &change["after"]);
+        if before.is_null() {
+            db.remove(key);
+        } else if after.is_null() {
+            db[key] = before.clone();
+        } else if !db.has_key(key) {
+            // Only the changed fields were recorded, not the whole paper.
+            eprintln!("Skipping {}, which has been removed since", key);
+        } else {
+            for (field, _) in after.entries() {
+                if !before.has_key(field) {
+                    db[key].remove(field);
+                }
+            }
+            for (field, value) in before.entries() {
+                db[key][field] = value.clone();
+            }
+        }
+    }
+}
+
+/// One line per operation and one per changed paper.
+pub fn describe(operation: &json::JsonValue) -> String {
+    let mut lines = vec![format!(
+        "{} {} {}",
+        operation["id"], operation["time"], operation["command"]
+    )];
+    for change in operation["changes"].members() {
+        let line = match (change["before"].is_null(), change["after"].is_null()) {
+            (true, _) => format!("  + {}", change["key"]),
+            (_, true) => format!("  - {}", change["key"]),
+            _ => {
+                let mut fields = change["before"].entries().map(|(field, _)| field.to_string()).collect::<Vec<_>>();
+                for (field, _) in change["after"].entries() {
+                    if !fields.iter().any(|existing| existing == field) {
+                        fields.push(field.to_string());
+                    }
+                }
+                format!("  ~ {}: {}", change["key"], fields.join(", "))
+            },
+        };
+        lines.push(line);
+    }
+    return lines.join("\n")
+}
+
+#[cfg(test)]
+fn sample_db() -> json::JsonValue {
+    json::object!{
+        "a": {"title": "First", "year": "2017"},
+        "b": {"title": "Second", "tags": ["dp"]},
+    }
+}
+
+#[test]
+fn diff_added_removed_and_changed() {
+    let before = sample_db();
+    let mut after = sample_db();
+    after.remove("a");
+    after["b"]["title"] = json::from("foo");
+    after["b"].remove("tags");
+    after["c"] = json::object!{"title": "Third"};
+    let changes = diff(&before, &after);
+    assert_eq!(changes.len(), 3);
+    assert_eq!(changes[0], json::object!{"key": "a", "before": before["a"].clone(), "after": null});
+    assert_eq!(changes[1], json::object!{"key": "b", "before": {"title": "Second", "tags": ["dp"]}, "after": {"title": "foo"}});
+    assert_eq!(changes[2], json::object!{"key": "c", "before": null, "after": {"title": "Third"}});
+}
+
+#[test]
+fn revert_restores_database() {
+    let before = sample_db();
+    let mut after = sample_db();
+    after.remove("a");
+    after["b"]["title"] = json::from("foo");
+    after["b"]["year"] = json::from("2020");
+    after["c"] = json::object!{"title": "Third"};
+    let operation = json::object!{"id": 0, "changes": json::from(diff(&before, &after))};
+    revert(&mut after, &operation);
+    assert_eq!(after["a"], before["a"]);
+    assert_eq!(after["b"], before["b"]);
+    assert!(!after.has_key("c"));
+}
+
+#[test]
+fn revert_edit_of_removed_paper() {
+    let before = sample_db();
+    let mut after = sample_db();
+    after["b"]["title"] = json::from("foo");
+    let operation = json::object!{"id": 0, "changes": json::from(diff(&before, &after))};
+    after.remove("b");
+    revert(&mut after, &operation);
+    assert!(!after.has_key("b"));
+    assert_eq!(after["a"], before["a"]);
+}
+
+#[test]
+fn undoable_skips_undone_operations() {
+    let journal = vec![
+        json::object!{"id": 0},
+        json::object!{"id": 1},
+        json::object!{"id": 2},
+        json::object!{"id": 3, "undoes": [2]},
+    ];
+    let ids = undoable(&journal, 2).iter().map(|operation| operation["id"].as_usize().unwrap()).collect::<Vec<_>>();
+    assert_eq!(ids, vec![1, 0]);
+}
+
+#[test]
+fn record_skips_timestamp_updates() {
+    let path = std::env::temp_dir().join(format!("paperman-journal-{}.jsonl", std::process::id()));
+    let before = sample_db();
+    let mut opened = sample_db();
+    opened["a"][crate::timestamps::LAST_OPENED] = json::from("2024-01-01");
+    assert_eq!(record(&path, &before, &opened, "open", &[]), None);
+    let mut edited = opened.clone();
+    edited["a"]["title"] = json::from("foo");
+    edited["a"][crate::timestamps::MODIFIED] = json::from("2024-01-02");
+    let operation = record(&path, &opened, &edited, "update title foo", &[]).unwrap();
+    let journal = load(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(journal, vec![operation]);
+    assert_eq!(undoable(&journal, 1)[0]["command"], "update title foo");
+}
@@ -1,123 +1,514 @@
 use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
 use logos::Logos;
+use crate::citation::{escape_html, Markup};
 
 #[derive(Logos, Debug, PartialEq)]
 enum Token<'a> {
     #[regex(r"[^\{}]", |lex| lex.slice())]
     Char(&'a str),
 
-    #[regex("[{}]")]
-    Brace,
+    #[token("{")]
+    OpenBrace,
 
-    #[regex(r#"\\`[\{\\}]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{0300}'))]
-    Grave(String),
+    #[token("}")]
+    CloseBrace,
 
-    #[regex(r#"\\'[\{\\}]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{0301}'))]
-    Acute(String),
+    // Accents written with a symbol, such as \"a, \'{e} or \^{\i}.
+    #[regex(r#"\\[`'^"~=.][\{\\}]*[a-zA-Z]"#, |lex| accented(lex.slice()))]
+    SymbolAccent(String),
 
-    #[regex(r#"\\\^[\{\\]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{0302}'))]
-    Circumflex(String),
+    // Accents written with a letter need a space or brace before the
+    // letter they go on, as in \c c or \v{s}. Written together, as in \vs,
+    // they are read as a command.
+    #[regex(r#"\\[HckbdruvG][ \{\\]+[a-zA-Z]"#, |lex| accented(lex.slice()))]
+    LetterAccent(String),
 
-    #[regex(r#"\\"[\{\\}]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{0308}'))]
-    Umlaut(String),
+    // The tie goes over two letters, as in \t{oo}, and like other letter
+    // accents needs a space or brace so that \tau is a command.
+    #[regex(r#"\\t[ \{]+[a-zA-Z][a-zA-Z]"#, |lex| tied(lex.slice()))]
+    Tie(String),
 
-    #[regex(r#"\\H[\{\\}]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{030B}'))]
-    HungarianUmlaut(String),
+    // The command with any spaces after it, which only end its name.
+    #[regex(r"\\[a-zA-Z]+ *", |lex| &lex.slice()[1..], priority = 4)]
+    Command(&'a str),
 
-    #[regex(r#"\\~[\{\\}]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{0303}'))]
-    Tilde(String),
+    #[regex(r"\$[^$]*\$", |lex| &lex.slice()[1..lex.slice().len() - 1])]
+    Math(&'a str),
 
-    #[regex(r#"\\c[\{\\}]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{0327}'))]
-    Cedilla(String),
+    #[token("--")]
+    EnDash,
 
-    #[regex(r#"\\k[\{\\}]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{0328}'))]
-    Ogonek(String),
+    #[token("---")]
+    EmDash,
 
-    #[regex(r#"\\l"#, |_| String::from("\u{0142}"))]
-    Barredl(String),
+    #[token("~")]
+    NonBreakingSpace,
 
-    #[regex(r#"\\=[\{\\}]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{0304}'))]
-    Macron(String),
+    #[regex(r#"\\."#, |lex| String::from(&lex.slice()[1..]))]
+    Backslash(String),
 
-    #[regex(r#"\\b[\{\\}]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{0332}'))]
-    BarUnder(String),
+    #[error]
+    Error,
+}
+
+/// Accent commands and the combining characters they add.
+pub const ACCENTS: [(char, char); 17] = [
+    ('`', '\u{0300}'),
+    ('\'', '\u{0301}'),
+    ('^', '\u{0302}'),
+    ('"', '\u{0308}'),
+    ('H', '\u{030B}'),
+    ('~', '\u{0303}'),
+    ('c', '\u{0327}'),
+    ('k', '\u{0328}'),
+    ('=', '\u{0304}'),
+    ('b', '\u{0331}'),
+    ('.', '\u{0307}'),
+    ('d', '\u{0323}'),
+    ('r', '\u{030A}'),
+    ('u', '\u{0306}'),
+    ('v', '\u{030C}'),
+    ('G', '\u{030F}'),
+    ('t', '\u{0361}'),
+];
+
+/// Letters with a command of their own.
+pub const SPECIAL_LETTERS: [(&str, &str); 22] = [
+    ("i", "ı"),
+    ("j", "ȷ"),
+    ("l", "ł"),
+    ("L", "Ł"),
+    ("o", "ø"),
+    ("O", "Ø"),
+    ("aa", "å"),
+    ("AA", "Å"),
+    ("ae", "æ"),
+    ("AE", "Æ"),
+    ("oe", "œ"),
+    ("OE", "Œ"),
+    ("ss", "ß"),
+    ("SS", "SS"),
+    ("dh", "ð"),
+    ("DH", "Ð"),
+    ("th", "þ"),
+    ("TH", "Þ"),
+    ("ng", "ŋ"),
+    ("NG", "Ŋ"),
+    ("dj", "đ"),
+    ("DJ", "Đ"),
+];
+
+fn combiner(command: char) -> Option<char> {
+    return ACCENTS.iter().find(|(accent, _)| *accent == command).map(|(_, combiner)| *combiner)
+}
+
+/// The last letter of an accent command with its combining accent.
+fn accented(slice: &str) -> String {
+    let command = slice.chars().nth(1).unwrap();
+    let mut result = String::from(&slice[slice.len() - 1..]);
+    result.push(combiner(command).unwrap());
+    return result
+}
 
-    #[regex(r#"\\\.[\{\\}]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{0307}'))]
-    DotOver(String),
+/// The two letters of a tie command joined by the double inverted breve.
+fn tied(slice: &str) -> String {
+    let letters = &slice[slice.len() - 2..];
+    return format!("{}{}{}", &letters[..1], combiner('t').unwrap(), &letters[1..])
+}
 
-    #[regex(r#"\\d[\{\\}]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{0323}'))]
-    DotUnder(String),
+/// A command outside math: a special letter, a text command or a letter
+/// accent written together with its letter, as in \vs. Spaces after known
+/// commands are dropped, like LaTeX does, and others keep them.
+fn text_command(command: &str) -> String {
+    let name = command.trim_end();
+    if let Some(text) = lookup(&SPECIAL_LETTERS, name).or_else(|| lookup(&TEXT_COMMANDS, name)) {
+        return text.to_string()
+    }
+    let spaces = &command[name.len()..];
+    let mut chars = name.chars();
+    if let (Some(accent), Some(letter), None) = (chars.next(), chars.next(), chars.next()) {
+        if let Some(combiner) = combiner(accent).filter(|_| accent != 't') {
+            return format!("{}{}{}", letter, combiner, spaces)
+        }
+    }
+    return command.to_string()
+}
 
-    #[regex(r#"\\r[\{\\}]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{030A}'))]
-    RingOver(String),
+#[derive(Logos, Debug, PartialEq)]
+enum MathToken<'a> {
+    #[regex(r"[^\\{}\^_]", |lex| lex.slice())]
+    Char(&'a str),
 
-    #[regex(r#"\\u[\{\\}]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{0306}'))]
-    Breve(String),
+    #[regex("[{}]")]
+    Brace,
 
-    #[regex(r#"\\v[\{\\}]*[a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{030C}'))]
-    Caron(String),
+    #[regex(r"\\[a-zA-Z]+", |lex| &lex.slice()[1..])]
+    Command(&'a str),
 
-    #[regex(r#"\\t[\{\\}]*[a-zA-Z][a-zA-Z]"#, |lex| add_diacritic(lex.slice(), '\u{0308}'))]
-    Tie(String),
+    #[regex(r"\\[^a-zA-Z]", |lex| &lex.slice()[1..])]
+    Escaped(&'a str),
 
-    #[regex(r#"\\\{*o"#, |_| String::from("\u{00F8}"))]
-    SlashedO(String),
+    #[regex(r"\^(\{[^}]*\}|\\[a-zA-Z]+|[^\\{])", |lex| group(lex.slice()))]
+    Superscript(&'a str),
 
-    #[regex(r#"\\aa"#, |_| String::from("å"))]
-    RingOverA(String),
-    
-    #[regex(r#"\\."#, |lex| String::from(&lex.slice()[1..]))]
-    Backslash(String),
+    #[regex(r"_(\{[^}]*\}|\\[a-zA-Z]+|[^\\{])", |lex| group(lex.slice()))]
+    Subscript(&'a str),
 
     #[error]
     Error,
 }
 
-fn add_diacritic<'c, 'd>(slice: &str, combiner: char) -> String {
-    let mut result = String::new();
-    result.push_str(&slice[slice.len() - 1..slice.len()]);
-    result.push(combiner);
-    return result
+// Commands outside math, replaced by their text. Formatting commands keep
+// only their argument, and unknown commands are written by their name.
+const TEXT_COMMANDS: [(&str, &str); 34] = [
+    ("emph", ""),
+    ("textbf", ""),
+    ("textit", ""),
+    ("textsl", ""),
+    ("textsc", ""),
+    ("texttt", ""),
+    ("textrm", ""),
+    ("textsf", ""),
+    ("textup", ""),
+    ("textnormal", ""),
+    ("mbox", ""),
+    ("em", ""),
+    ("it", ""),
+    ("sl", ""),
+    ("bf", ""),
+    ("rm", ""),
+    ("sf", ""),
+    ("sc", ""),
+    ("upshape", ""),
+    ("rmfamily", ""),
+    ("sffamily", ""),
+    ("scshape", ""),
+    ("tt", ""),
+    ("itshape", ""),
+    ("bfseries", ""),
+    ("ttfamily", ""),
+    ("ldots", "…"),
+    ("dots", "…"),
+    ("textendash", "\u{2013}"),
+    ("textemdash", "\u{2014}"),
+    ("copyright", "©"),
+    ("pounds", "£"),
+    ("S", "§"),
+    ("P", "¶"),
+];
+
+// Commands rendered as emphasis, strong text or code, with their HTML tag
+// and Markdown marker. Commands taking an argument style it, while the
+// others style the rest of their group, as in {\em word}.
+const FORMATTING: [(&str, &str, &str, bool); 13] = [
+    ("emph", "em", "*", true),
+    ("textit", "em", "*", true),
+    ("textsl", "em", "*", true),
+    ("textbf", "strong", "**", true),
+    ("texttt", "code", "`", true),
+    ("em", "em", "*", false),
+    ("it", "em", "*", false),
+    ("itshape", "em", "*", false),
+    ("sl", "em", "*", false),
+    ("bf", "strong", "**", false),
+    ("bfseries", "strong", "**", false),
+    ("tt", "code", "`", false),
+    ("ttfamily", "code", "`", false),
+];
+
+// Commands in math mode. Fonts keep only their argument.
+const MATH_COMMANDS: [(&str, &str); 83] = [
+    ("alpha", "α"), ("beta", "β"), ("gamma", "γ"), ("delta", "δ"),
+    ("epsilon", "ϵ"), ("varepsilon", "ε"), ("zeta", "ζ"), ("eta", "η"),
+    ("theta", "θ"), ("vartheta", "ϑ"), ("iota", "ι"), ("kappa", "κ"),
+    ("lambda", "λ"), ("mu", "μ"), ("nu", "ν"), ("xi", "ξ"),
+    ("pi", "π"), ("varpi", "ϖ"), ("rho", "ρ"), ("varrho", "ϱ"),
+    ("sigma", "σ"), ("varsigma", "ς"), ("tau", "τ"), ("upsilon", "υ"),
+    ("phi", "ϕ"), ("varphi", "φ"), ("chi", "χ"), ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Gamma", "Γ"), ("Delta", "Δ"), ("Theta", "Θ"), ("Lambda", "Λ"),
+    ("Xi", "Ξ"), ("Pi", "Π"), ("Sigma", "Σ"), ("Upsilon", "Υ"),
+    ("Phi", "Φ"), ("Psi", "Ψ"), ("Omega", "Ω"),
+    ("infty", "∞"), ("leq", "≤"), ("le", "≤"), ("geq", "≥"),
+    ("ge", "≥"), ("neq", "≠"), ("ne", "≠"), ("approx", "≈"),
+    ("sim", "∼"), ("cdot", "⋅"), ("times", "×"), ("pm", "±"),
+    ("in", "∈"), ("notin", "∉"), ("subset", "⊂"), ("subseteq", "⊆"),
+    ("cup", "∪"), ("cap", "∩"), ("sum", "∑"), ("prod", "∏"),
+    ("sqrt", "√"), ("partial", "∂"), ("nabla", "∇"), ("forall", "∀"),
+    ("exists", "∃"), ("to", "→"), ("rightarrow", "→"), ("leftarrow", "←"),
+    ("Rightarrow", "⇒"), ("mapsto", "↦"), ("ell", "ℓ"), ("ldots", "…"),
+    ("cdots", "⋯"), ("prime", "′"),
+    ("mathrm", ""), ("mathbf", ""), ("mathit", ""), ("mathsf", ""),
+    ("mathtt", ""), ("mathcal", ""), ("mathbb", ""), ("text", ""),
+    ("operatorname", ""),
+];
+
+// Operators such as \log, written by their name.
+const MATH_FUNCTIONS: [&str; 22] = [
+    "log", "ln", "exp", "lg", "sin", "cos", "tan", "arg", "max", "min", "sup", "inf",
+    "lim", "liminf", "limsup", "det", "dim", "deg", "ker", "gcd", "Pr", "poly",
+];
+
+// Accents over math, put on each character of their argument.
+const MATH_ACCENTS: [(&str, char); 12] = [
+    ("hat", '\u{0302}'), ("widehat", '\u{0302}'), ("tilde", '\u{0303}'), ("widetilde", '\u{0303}'),
+    ("bar", '\u{0304}'), ("overline", '\u{0305}'), ("breve", '\u{0306}'), ("dot", '\u{0307}'),
+    ("ddot", '\u{0308}'), ("check", '\u{030C}'), ("acute", '\u{0301}'), ("vec", '\u{20D7}'),
+];
+
+const SUPERSCRIPTS: [(char, char); 45] = [
+    ('0', '⁰'), ('1', '¹'), ('2', '²'), ('3', '³'), ('4', '⁴'),
+    ('5', '⁵'), ('6', '⁶'), ('7', '⁷'), ('8', '⁸'), ('9', '⁹'),
+    ('+', '⁺'), ('-', '⁻'), ('=', '⁼'), ('(', '⁽'), (')', '⁾'),
+    ('a', 'ᵃ'), ('b', 'ᵇ'), ('c', 'ᶜ'), ('d', 'ᵈ'), ('e', 'ᵉ'),
+    ('f', 'ᶠ'), ('g', 'ᵍ'), ('h', 'ʰ'), ('i', 'ⁱ'), ('j', 'ʲ'),
+    ('k', 'ᵏ'), ('l', 'ˡ'), ('m', 'ᵐ'), ('n', 'ⁿ'), ('o', 'ᵒ'),
+    ('p', 'ᵖ'), ('r', 'ʳ'), ('s', 'ˢ'), ('t', 'ᵗ'), ('u', 'ᵘ'),
+    ('v', 'ᵛ'), ('w', 'ʷ'), ('x', 'ˣ'), ('y', 'ʸ'), ('z', 'ᶻ'),
+    ('T', 'ᵀ'), ('*', '*'), ('′', '′'), ('\'', '′'), (' ', ' '),
+];
+
+const SUBSCRIPTS: [(char, char); 33] = [
+    ('0', '₀'), ('1', '₁'), ('2', '₂'), ('3', '₃'), ('4', '₄'),
+    ('5', '₅'), ('6', '₆'), ('7', '₇'), ('8', '₈'), ('9', '₉'),
+    ('+', '₊'), ('-', '₋'), ('=', '₌'), ('(', '₍'), (')', '₎'),
+    ('a', 'ₐ'), ('e', 'ₑ'), ('o', 'ₒ'), ('x', 'ₓ'), ('h', 'ₕ'),
+    ('k', 'ₖ'), ('l', 'ₗ'), ('m', 'ₘ'), ('n', 'ₙ'), ('p', 'ₚ'),
+    ('s', 'ₛ'), ('t', 'ₜ'), ('i', 'ᵢ'), ('j', 'ⱼ'), ('r', 'ᵣ'),
+    ('u', 'ᵤ'), ('v', 'ᵥ'), (' ', ' '),
+];
+
+fn lookup<'a>(table: &[(&str, &'a str)], name: &str) -> Option<&'a str> {
+    return table.iter().find(|(command, _)| *command == name).map(|(_, text)| *text)
+}
+
+/// The argument of a sub- or superscript, without the braces around it.
+fn group(slice: &str) -> &str {
+    let argument = &slice[1..];
+    return argument.strip_prefix('{').and_then(|argument| argument.strip_suffix('}')).unwrap_or(argument)
+}
+
+/// The argument of a math command at the start of `text`, without braces,
+/// and the length of `text` it takes up. An argument without braces is a
+/// command or a single character.
+fn math_argument(text: &str) -> Option<(&str, usize)> {
+    let start = text.len() - text.trim_start().len();
+    let rest = &text[start..];
+    let first = rest.chars().next()?;
+    let end = match first {
+        '{' => {
+            let mut depth = 0;
+            let close = rest.char_indices().find(|(_, c)| {
+                match c {
+                    '{' => depth += 1,
+                    '}' => depth -= 1,
+                    _ => (),
+                }
+                depth == 0
+            })?.0;
+            return Some((&rest[1..close], start + close + 1))
+        },
+        '}' => return None,
+        // A command name, or an escaped character, or a lone backslash.
+        '\\' => match rest[1..].find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(rest.len() - 1) {
+            0 => 1 + rest[1..].chars().next().map_or(0, char::len_utf8),
+            letters => 1 + letters,
+        },
+        first => first.len_utf8(),
+    };
+    return Some((&rest[..end], start + end))
+}
+
+/// A math command and its arguments as text. Returns the text and the
+/// length of `rest`, following the command, taken up by the arguments.
+fn math_command(name: &str, rest: &str) -> (String, usize) {
+    if let Some(text) = lookup(&MATH_COMMANDS, name) {
+        return (text.to_string(), 0)
+    }
+    if MATH_FUNCTIONS.contains(&name) {
+        return (name.to_string(), 0)
+    }
+    if let Some((_, accent)) = MATH_ACCENTS.iter().find(|(command, _)| *command == name) {
+        if let Some((argument, length)) = math_argument(rest) {
+            let accented = decode_math(argument).chars()
+                .flat_map(|c| vec![c, *accent])
+                .collect::<String>();
+            return (accented, length)
+        }
+    }
+    if name == "frac" {
+        if let Some((numerator, first)) = math_argument(rest) {
+            if let Some((denominator, second)) = math_argument(&rest[first..]) {
+                let part = |text: &str| {
+                    let text = decode_math(text);
+                    if text.chars().filter(|c| !is_combining_mark(*c)).count() > 1 { format!("({})", text) } else { text }
+                };
+                return (format!("{}/{}", part(numerator), part(denominator)), first + second)
+            }
+        }
+    }
+    // Unknown commands are kept as they are.
+    if rest.starts_with('{') {
+        if let Some((argument, length)) = math_argument(rest) {
+            return (format!("\\{}{{{}}}", name, decode_math(argument)), length)
+        }
+    }
+    return (format!("\\{}", name), 0)
+}
+
+/// Raise or lower text with Unicode super- or subscripts, or write it after
+/// the marker if a character has none.
+fn shift(text: &str, table: &[(char, char)], marker: char) -> String {
+    let shifted = text.chars()
+        .map(|c| table.iter().find(|(plain, _)| *plain == c).map(|(_, shifted)| *shifted))
+        .collect::<Option<String>>();
+    match shifted {
+        Some(shifted) => return shifted,
+        None if text.chars().count() == 1 => return format!("{}{}", marker, text),
+        None => return format!("{}({})", marker, text),
+    }
 }
 
-pub fn decode_latex(latex: &str) -> String {
+/// Inline math as plain text, with Greek letters and symbols in Unicode.
+pub fn decode_math(math: &str) -> String {
     let mut result = String::new();
-    let mut lex = Token::lexer(latex);
+    let mut lex = MathToken::lexer(math);
     loop {
         match lex.next() {
-            Some(Token::Char(c)) => result.push_str(c),
-            Some(Token::Brace) => (),
-            // Some(Token::Combiner(comb)) => (),
-            Some(Token::Grave(s)) => result.push_str(&s),
-            Some(Token::Acute(s)) => result.push_str(&s),
-            Some(Token::Circumflex(s)) => result.push_str(&s),
-            Some(Token::Umlaut(s)) => result.push_str(&s),
-            Some(Token::HungarianUmlaut(s)) => result.push_str(&s),
-            Some(Token::Tilde(s)) => result.push_str(&s),
-            Some(Token::Cedilla(s)) => result.push_str(&s),
-            Some(Token::Ogonek(s)) => result.push_str(&s),
-            Some(Token::Barredl(s)) => result.push_str(&s),
-            Some(Token::Macron(s)) => result.push_str(&s),
-            Some(Token::BarUnder(s)) => result.push_str(&s),
-            Some(Token::DotOver(s)) => result.push_str(&s),
-            Some(Token::DotUnder(s)) => result.push_str(&s),
-            Some(Token::RingOver(s)) => result.push_str(&s),
-            Some(Token::RingOverA(s)) => result.push_str(&s),
-            Some(Token::Breve(s)) => result.push_str(&s),
-            Some(Token::Caron(s)) => result.push_str(&s),
-            Some(Token::Tie(s)) => result.push_str(&s),
-            Some(Token::SlashedO(s)) => result.push_str(&s),
-            // Some(Token::Umlaut(s)) => result.push_str(&s),
-            // Some(Token::Command(command)) => (),
-            Some(Token::Backslash(s)) => result.push_str(&s),
-            Some(Token::Error) => (),
+            Some(MathToken::Char(c)) => result.push_str(c),
+            Some(MathToken::Brace) => (),
+            Some(MathToken::Command(name)) => {
+                let (text, length) = math_command(name, lex.remainder());
+                result.push_str(&text);
+                lex.bump(length);
+            },
+            Some(MathToken::Escaped(c)) => match c {
+                "," | ";" | ":" | " " => result.push(' '),
+                "!" => (),
+                c => result.push_str(c),
+            },
+            Some(MathToken::Superscript(s)) => result.push_str(&shift(&decode_math(s), &SUPERSCRIPTS, '^')),
+            Some(MathToken::Subscript(s)) => result.push_str(&shift(&decode_math(s), &SUBSCRIPTS, '_')),
+            Some(MathToken::Error) => (),
             None => break
         }
     }
+    return result
+}
 
+/// The text of a token outside math.
+fn token_text(token: Token) -> String {
+    match token {
+        Token::Char(c) => return c.to_string(),
+        Token::SymbolAccent(s) | Token::LetterAccent(s) | Token::Tie(s) | Token::Backslash(s) => return s,
+        Token::Command(name) => return text_command(name),
+        Token::Math(math) => return decode_math(math),
+        Token::EnDash => return String::from("\u{2013}"),
+        Token::EmDash => return String::from("\u{2014}"),
+        Token::NonBreakingSpace => return String::from("\u{00A0}"),
+        Token::OpenBrace | Token::CloseBrace | Token::Error => return String::new(),
+    }
+}
+
+pub fn decode_latex(latex: &str) -> String {
+    let result = Token::lexer(latex).map(token_text).collect::<String>();
     return result.nfc().collect::<String>();
 }
 
+fn escape(text: &str, markup: Markup) -> String {
+    match markup {
+        Markup::Plain => return text.to_string(),
+        Markup::Html => return escape_html(text),
+        Markup::Markdown => {
+            let mut escaped = String::new();
+            for c in text.chars() {
+                if ['\\', '*', '_', '`', '[', ']'].contains(&c) {
+                    escaped.push('\\');
+                }
+                escaped.push(c);
+            }
+            return escaped
+        },
+    }
+}
+
+/// Markup opening and closing a formatting command.
+fn formatting(name: &str, markup: Markup) -> Option<(String, String, bool)> {
+    let (_, tag, marker, argument) = FORMATTING.iter().find(|(command, _, _, _)| *command == name)?;
+    match markup {
+        Markup::Plain => return Some((String::new(), String::new(), *argument)),
+        Markup::Markdown => return Some((marker.to_string(), marker.to_string(), *argument)),
+        Markup::Html => return Some((format!("<{}>", tag), format!("</{}>", tag), *argument)),
+    }
+}
+
+/// Inline math for the web: KaTeX-ready spans in HTML, dollars in
+/// Markdown and Unicode in plain text.
+fn render_math(math: &str, markup: Markup) -> String {
+    match markup {
+        Markup::Plain => return decode_math(math),
+        Markup::Markdown => return format!("${}$", math),
+        Markup::Html => return format!("<span class=\"math inline\">\\({}\\)</span>", escape(math, markup)),
+    }
+}
+
+/// Render LaTeX text such as a title or note as HTML or Markdown, keeping
+/// emphasis and math that decode_latex throws away.
+pub fn render_latex(latex: &str, markup: Markup) -> String {
+    let mut result = String::new();
+    // The markup closing each open group, outermost first.
+    let mut groups = vec![String::new()];
+    // Closing markup for a formatting command waiting for its argument.
+    let mut argument: Option<String> = None;
+    let mut skip_space = false;
+    for token in Token::lexer(latex) {
+        let text = match token {
+            Token::OpenBrace => {
+                groups.push(argument.take().unwrap_or_default());
+                continue
+            },
+            Token::CloseBrace => {
+                if groups.len() > 1 {
+                    result.push_str(&groups.pop().unwrap());
+                }
+                continue
+            },
+            Token::Command(name) => match formatting(name.trim_end(), markup) {
+                Some((open, close, true)) => {
+                    result.push_str(&open);
+                    argument = Some(close);
+                    continue
+                },
+                Some((open, close, false)) => {
+                    result.push_str(&open);
+                    groups.last_mut().unwrap().insert_str(0, &close);
+                    skip_space = true;
+                    continue
+                },
+                None => escape(&text_command(name), markup),
+            },
+            Token::Math(math) => render_math(math, markup),
+            token => escape(&token_text(token), markup),
+        };
+        if (argument.is_some() || skip_space) && text.trim().is_empty() {
+            continue
+        }
+        // An argument without braces is a single character.
+        if let Some(close) = argument.take() {
+            result.push_str(&text);
+            result.push_str(&close);
+            continue
+        }
+        skip_space = false;
+        result.push_str(&text);
+    }
+    while let Some(close) = groups.pop() {
+        result.push_str(&close);
+    }
+    return result.nfc().collect::<String>()
+}
+
 #[test]
 fn decode_plain_ascii_text() {
     let input = "Hello World! 123 _";
@@ -257,7 +648,6 @@ fn decode_ogonek() {
 fn decode_barred() {
     let result = decode_latex(r"Hello \l");
     assert_eq!(result, "Hello \u{0142}".nfc().collect::<String>());
-    //TODO: find correct character
 }
 #[test]
 fn decode_macron() {
@@ -285,11 +675,10 @@ fn decode_caron() {
     assert_eq!(result, "Hello s\u{030C}".nfc().collect::<String>());
 }
 #[test]
-#[ignore]
 fn decode_tie() {
-    let result = decode_latex(r"Hello \t");
-    assert_eq!(result, "Hello ".nfc().collect::<String>());
-    //TODO: find correct character
+    let result = decode_latex(r"Hello \t{oo}");
+    assert_eq!(result, "Hello o\u{0361}o");
+    assert_eq!(decode_latex(r"\t oo \tau $\tau$"), "o\u{0361}o tau τ");
 }
 #[test]
 fn decode_slashed() {
@@ -336,3 +725,119 @@ fn decode_complex_string() {
     let result = decode_latex(r"Hello \'{o in braces} world \{\~{n\`o}\} after");
     assert_eq!(result, "Hello ó in braces world {ñò} after");
 }
+
+#[test]
+fn decode_formatting_commands() {
+    assert_eq!(decode_latex(r"\emph{Sparse} Vector"), "Sparse Vector");
+    assert_eq!(decode_latex(r"\textbf{Bold} and \textit{italic}"), "Bold and italic");
+    assert_eq!(decode_latex(r"Unknown \foo{bar}"), "Unknown foobar");
+    assert_eq!(decode_latex(r"{\bf Bold} and {\rm Roman}"), "Bold and Roman");
+    assert_eq!(decode_latex(r"{\em Sparse} {\sc Vector}"), "Sparse Vector");
+}
+
+#[test]
+fn decode_ligatures() {
+    assert_eq!(decode_latex(r"Stra{\ss}e"), "Straße");
+    assert_eq!(decode_latex(r"\ae{}ther \OE{}uvre"), "æther Œuvre");
+}
+
+#[test]
+fn decode_punctuation() {
+    assert_eq!(decode_latex(r"Smith \& Wesson"), "Smith & Wesson");
+    assert_eq!(decode_latex("pages 12--34---or so"), "pages 12\u{2013}34\u{2014}or so");
+    assert_eq!(decode_latex("Fig.~3"), "Fig.\u{00A0}3");
+}
+
+#[test]
+fn decode_inline_math() {
+    assert_eq!(decode_latex(r"$\epsilon$-Differential Privacy"), "ϵ-Differential Privacy");
+    assert_eq!(decode_latex(r"$(\varepsilon, \delta)$-DP"), "(ε, δ)-DP");
+    assert_eq!(decode_latex(r"$O(n^2)$ and $\Omega(\log n)$"), "O(n²) and Ω(log n)");
+    assert_eq!(decode_latex(r"Price of \$5"), "Price of $5");
+}
+
+#[test]
+fn decode_math_scripts() {
+    assert_eq!(decode_math("x_{ij}^{2n}"), "xᵢⱼ²ⁿ");
+    assert_eq!(decode_math(r"\ell_1"), "ℓ₁");
+    assert_eq!(decode_math(r"x^{\alpha}"), "x^α");
+    assert_eq!(decode_math("x_{by}"), "x_(by)");
+    assert_eq!(decode_math(r"\mathbb{R}^d"), "Rᵈ");
+}
+
+#[test]
+fn decode_math_accents_and_fractions() {
+    assert_eq!(decode_math(r"\hat{x} + \bar y"), "x\u{0302} + y\u{0304}");
+    assert_eq!(decode_math(r"\tilde{\theta}"), "θ\u{0303}");
+    assert_eq!(decode_math(r"\frac{a}{b}"), "a/b");
+    assert_eq!(decode_math(r"\frac{n+1}{2}"), "(n+1)/2");
+    assert_eq!(decode_math(r"\frac12"), "1/2");
+    assert_eq!(decode_math(r"\frac{\hat{x}}{\sqrt{n}}"), "x\u{0302}/(√n)");
+    assert_eq!(decode_math(r"\log n \mathfrak{g} \wp"), "log n \\mathfrak{g} \\wp");
+}
+
+#[test]
+fn decode_math_arguments_at_the_end() {
+    assert_eq!(decode_latex("$\\hat\\$"), "");
+    assert_eq!(decode_latex("$\\hat\\é$"), "é\u{0302}");
+    assert_eq!(decode_latex("$\\frac{a}\\$"), "a/");
+    assert_eq!(decode_latex("$\\hat$"), "\\hat");
+}
+
+#[test]
+fn decode_special_letters() {
+    for (command, letter) in SPECIAL_LETTERS.iter() {
+        assert_eq!(decode_latex(&format!("{{\\{}}}", command)), *letter);
+        assert_eq!(decode_latex(&format!("\\{}{{}}x", command)), format!("{}x", letter));
+    }
+    assert_eq!(decode_latex(r"{\L}{\'o}d{\'z}"), "Łódź");
+    assert_eq!(decode_latex(r#"{\AA}ngstr{\"o}m and {\O}rsted"#), "Ångström and Ørsted");
+    assert_eq!(decode_latex(r"{\TH}{\dh}{\ng}"), "Þðŋ");
+}
+
+#[test]
+fn decode_uppercase_accents() {
+    assert_eq!(decode_latex(r#"\'E\`A\^O\"U\~N\=I\.Z"#), "ÉÀÔÜÑĪŻ");
+    assert_eq!(decode_latex(r"\c{C}\k{A}\v{S}\H{O}\u{G}\r{U}\d{S}"), "ÇĄŠŐĞŮṢ");
+}
+
+#[test]
+fn decode_letter_accents() {
+    assert_eq!(decode_latex(r"\c c \v{s} \k{\i}"), "ç š į");
+    assert_eq!(decode_latex(r"\b{k}\G{a}"), "ḵȁ");
+    assert_eq!(decode_latex(r"\u{\i}"), "ĭ");
+    assert_eq!(decode_latex(r"\'{\i}\'\i"), "íí");
+}
+
+#[test]
+fn decode_every_accent() {
+    for (command, combiner) in ACCENTS.iter().filter(|(command, _)| *command != 't') {
+        let expected = format!("a{}", combiner).nfc().collect::<String>();
+        assert_eq!(decode_latex(&format!("\\{}{{a}}", command)), expected);
+    }
+}
+
+#[test]
+fn render_emphasis() {
+    let title = r"\emph{Sparse} Vector for {\em all} \textbf{DP}";
+    assert_eq!(render_latex(title, Markup::Html), "<em>Sparse</em> Vector for <em>all</em> <strong>DP</strong>");
+    assert_eq!(render_latex(title, Markup::Markdown), "*Sparse* Vector for *all* **DP**");
+    assert_eq!(render_latex(title, Markup::Plain), "Sparse Vector for all DP");
+}
+
+#[test]
+fn render_math_and_text() {
+    let title = r"$\epsilon$-DP for R\'enyi \& \texttt{x<y}";
+    assert_eq!(
+        render_latex(title, Markup::Html),
+        r#"<span class="math inline">\(\epsilon\)</span>-DP for Rényi &amp; <code>x&lt;y</code>"#
+    );
+    assert_eq!(render_latex(title, Markup::Markdown), r"$\epsilon$-DP for Rényi & `x<y`");
+    assert_eq!(render_latex(title, Markup::Plain), "ϵ-DP for Rényi & x<y");
+}
+
+#[test]
+fn render_unbalanced_braces() {
+    assert_eq!(render_latex(r"\emph{open", Markup::Html), "<em>open</em>");
+    assert_eq!(render_latex(r"close} \emph x_y", Markup::Markdown), r"close *x*\_y");
+}
@@ -0,0 +1,160 @@
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+use unidecode::unidecode;
+use crate::latex_decoder::{ACCENTS, SPECIAL_LETTERS};
+
+// The inverse of latex_decoder: writes non-ASCII text as LaTeX so that it
+// works with classic BibTeX and 8-bit fonts. ASCII is left as it is, since
+// field values are already LaTeX. Accented letters are split into a base
+// letter and combining accents, written like {\"{a}}, and characters with
+// no LaTeX form are transliterated.
+
+// Punctuation and symbols with a LaTeX form. Letters with a command of
+// their own are found in latex_decoder::SPECIAL_LETTERS.
+const SYMBOLS: [(char, &str); 13] = [
+    ('\u{2013}', "--"),
+    ('\u{2014}', "---"),
+    ('\u{2018}', "`"),
+    ('\u{2019}', "'"),
+    ('\u{201C}', "``"),
+    ('\u{201D}', "''"),
+    ('\u{00A0}', "~"),
+    ('\u{2026}', "{\\ldots}"),
+    ('§', "{\\S}"),
+    ('©', "{\\copyright}"),
+    ('£', "{\\pounds}"),
+    ('¿', "?`"),
+    ('¡', "!`"),
+];
+
+fn accent_command(mark: char) -> Option<char> {
+    ACCENTS.iter().find(|(_, combiner)| *combiner == mark).map(|(command, _)| *command)
+}
+
+/// LaTeX for a letter with combining accents, innermost accent first.
+fn encode_accented(letter: &str) -> Option<String> {
+    let decomposed = letter.nfd().collect::<Vec<_>>();
+    let (base, marks) = decomposed.split_first()?;
+    if !base.is_ascii_alphabetic() || marks.is_empty() {
+        return None
+    }
+    let mut result = match base {
+        // Accents go on the dotless i and j.
+        'i' => String::from("\\i"),
+        'j' => String::from("\\j"),
+        base => base.to_string(),
+    };
+    for mark in marks {
+        result = format!("\\{}{{{}}}", accent_command(*mark)?, result);
+    }
+    return Some(format!("{{{}}}", result))
+}
+
+pub fn encode_latex(text: &str) -> String {
+    let mut result = String::new();
+    let mut chars = text.nfc().peekable();
+    while let Some(c) = chars.next() {
+        // Accents with no precomposed letter follow their base letter.
+        let mut marks = String::new();
+        while let Some(mark) = chars.next_if(|next| is_combining_mark(*next)) {
+            marks.push(mark);
+        }
+        let accented = if marks.is_empty() { None } else { encode_accented(&format!("{}{}", c, marks)) };
+        if let Some(latex) = accented {
+            result.push_str(&latex);
+        } else if c.is_ascii() {
+            result.push(c);
+        } else if let Some((command, _)) = SPECIAL_LETTERS.iter().find(|(_, letter)| letter.chars().eq(std::iter::once(c))) {
+            result.push_str(&format!("{{\\{}}}", command));
+        } else if let Some((_, latex)) = SYMBOLS.iter().find(|(symbol, _)| *symbol == c) {
+            result.push_str(latex);
+        } else if let Some(latex) = encode_accented(&c.to_string()) {
+            result.push_str(&latex);
+        } else {
+            result.push_str(&unidecode(&c.to_string()));
+        }
+    }
+    return result
+}
+
+// Fields holding paths and identifiers rather than text.
+const VERBATIM_FIELDS: [&str; 4] = ["file", "url", "doi", "entry_type"];
+
+/// Encode the text fields of each paper of a selection, including the items
+/// of list fields such as tags.
+pub fn encode_selection(mut selection: json::JsonValue) -> json::JsonValue {
+    for (_, paper) in selection.entries_mut() {
+        for (field, value) in paper.entries_mut() {
+            if VERBATIM_FIELDS.contains(&field) {
+                continue
+            }
+            if let Some(text) = value.as_str() {
+                *value = json::from(encode_latex(text));
+            }
+            for item in value.members_mut() {
+                if let Some(text) = item.as_str() {
+                    *item = json::from(encode_latex(text));
+                }
+            }
+        }
+    }
+    return selection
+}
+
+#[test]
+fn encode_ascii_unchanged() {
+    let input = r"Hello {World}! 123 _ \'{e}";
+    assert_eq!(encode_latex(input), input);
+}
+
+#[test]
+fn encode_accents() {
+    assert_eq!(encode_latex("Räisä"), "R{\\\"{a}}is{\\\"{a}}");
+    assert_eq!(encode_latex("Ångström"), "{\\AA}ngstr{\\\"{o}}m");
+    assert_eq!(encode_latex("Erdős"), "Erd{\\H{o}}s");
+    assert_eq!(encode_latex("naïve"), "na{\\\"{\\i}}ve");
+    assert_eq!(encode_latex("ǖ"), "{\\={\\\"{u}}}");
+    assert_eq!(encode_latex("S\u{0300}"), "{\\`{S}}");
+}
+
+#[test]
+fn encode_symbols() {
+    assert_eq!(encode_latex("Łódź"), "{\\L}{\\'{o}}d{\\'{z}}");
+    assert_eq!(encode_latex("Straße 12–14"), "Stra{\\ss}e 12--14");
+    assert_eq!(encode_latex("“Quoted”…"), "``Quoted''{\\ldots}");
+}
+
+#[test]
+fn encode_unknown_characters_as_ascii() {
+    assert_eq!(encode_latex("α"), "a");
+    assert!(encode_latex("東京").is_ascii());
+}
+
+#[test]
+fn encode_papers() {
+    let selection = json::object!{"k": {
+        "author": "Gödel, Kurt",
+        "file": "/papers/gödel.pdf",
+        "tags": ["logik", "grundlagen–math"],
+    }};
+    assert_eq!(encode_selection(selection), json::object!{"k": {
+        "author": "G{\\\"{o}}del, Kurt",
+        "file": "/papers/gödel.pdf",
+        "tags": ["logik", "grundlagen--math"],
+    }});
+}
+
+#[test]
+fn round_trip_decoder_tables() {
+    // The tie joins two letters, see latex_decoder::decode_tie.
+    let accented = ACCENTS.iter()
+        .filter(|(command, _)| *command != 't')
+        .flat_map(|(_, combiner)| ['a', 'o', 'i', 'S'].iter().map(move |base| format!("{}{}", base, combiner)));
+    let special = SPECIAL_LETTERS.iter().map(|(_, letter)| letter.to_string());
+    for text in accented.chain(special) {
+        let text = text.nfc().collect::<String>();
+        let encoded = encode_latex(&text);
+        assert!(encoded.is_ascii(), "{} encoded as {}", text, encoded);
+        assert_eq!(crate::latex_decoder::decode_latex(&encoded), text);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::bibtex::{self, Dialect};
+
+// Required and optional fields of each entry type, after the BibTeX
+// documentation (btxdoc) and the BibLaTeX manual. Alternatives are
+// separated by `|`, so "author|editor" needs either of them. BibLaTeX
+// entries are checked after converting them with bibtex::to_biblatex, so
+// `date` stands in for the year.
+
+struct Rule {
+    entry_type: &'static str,
+    required: &'static [&'static str],
+    optional: &'static [&'static str],
+}
+
+const BIBTEX_RULES: [Rule; 14] = [
+    Rule { entry_type: "article", required: &["author", "title", "journal", "year"], optional: &["volume", "number", "pages", "month", "note"] },
+    Rule { entry_type: "book", required: &["author|editor", "title", "publisher", "year"], optional: &["volume", "number", "series", "address", "edition", "month", "note"] },
+    Rule { entry_type: "booklet", required: &["title"], optional: &["author", "howpublished", "address", "month", "year", "note"] },
+    Rule { entry_type: "conference", required: &["author", "title", "booktitle", "year"], optional: &["editor", "volume", "number", "series", "pages", "address", "month", "organization", "publisher", "note"] },
+    Rule { entry_type: "inbook", required: &["author|editor", "title", "chapter|pages", "publisher", "year"], optional: &["volume", "number", "series", "type", "address", "edition", "month", "note"] },
+    Rule { entry_type: "incollection", required: &["author", "title", "booktitle", "publisher", "year"], optional: &["editor", "volume", "number", "series", "type", "chapter", "pages", "address", "edition", "month", "note"] },
+    Rule { entry_type: "inproceedings", required: &["author", "title", "booktitle", "year"], optional: &["editor", "volume", "number", "series", "pages", "address", "month", "organization", "publisher", "note"] },
+    Rule { entry_type: "manual", required: &["title"], optional: &["author", "organization", "address", "edition", "month", "year", "note"] },
+    Rule { entry_type: "mastersthesis", required: &["author", "title", "school", "year"], optional: &["type", "address", "month", "note"] },
+    Rule { entry_type: "misc", required: &[], optional: &["author", "title", "howpublished", "month", "year", "note"] },
+    Rule { entry_type: "phdthesis", required: &["author", "title", "school", "year"], optional: &["type", "address", "month", "note"] },
+    Rule { entry_type: "proceedings", required: &["title", "year"], optional: &["editor", "volume", "number", "series", "address", "month", "organization", "publisher", "note"] },
+    Rule { entry_type: "techreport", required: &["author", "title", "institution", "year"], optional: &["type", "number", "address", "month", "note"] },
+    Rule { entry_type: "unpublished", required: &["author", "title", "note"], optional: &["month", "year"] },
+];
+
+// Fields BibLaTeX allows on most entry types.
+const BIBLATEX_OPTIONAL: &[&str] = &[
+    "subtitle", "titleaddon", "editor", "translator", "language", "series", "volume", "number",
+    "issue", "eid", "pages", "pagetotal", "edition", "publisher", "location", "organization",
+    "chapter", "howpublished", "version", "note", "addendum", "pubstate", "isbn", "issn",
+    "eprinttype", "eprintclass", "annotation",
+];
+
+const BIBLATEX_RULES: [Rule; 17] = [
+    Rule { entry_type: "article", required: &["author", "title", "journaltitle", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "book", required: &["author", "title", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "booklet", required: &["author|editor", "title", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "collection", required: &["editor", "title", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "inbook", required: &["author", "title", "booktitle", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "incollection", required: &["author", "title", "booktitle", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "inproceedings", required: &["author", "title", "booktitle", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "manual", required: &["author|editor", "title", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "misc", required: &["author|editor", "title", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "mvbook", required: &["author", "title", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "online", required: &["author|editor", "title", "date", "url"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "patent", required: &["author", "title", "number", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "periodical", required: &["editor", "title", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "proceedings", required: &["title", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "report", required: &["author", "title", "type", "institution", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "thesis", required: &["author", "title", "type", "institution", "date"], optional: BIBLATEX_OPTIONAL },
+    Rule { entry_type: "unpublished", required: &["author", "title", "date"], optional: BIBLATEX_OPTIONAL },
+];
+
+// Fields allowed on any entry: identifiers and links, fields added by
+// bibliography databases, and paperman's own bookkeeping.
+const COMMON_FIELDS: &[&str] = &[
+    "entry_type", "key", "crossref", "url", "urldate", "doi", "eprint", "archiveprefix",
+    "primaryclass", "isbn", "issn", "abstract", "keywords", "annote", "language",
+    "timestamp", "biburl", "bibsource", "file", "tags", "status", "rating",
+    "added", "modified", "last_opened",
+];
+
+/// A problem found in a paper, with the fixed value of the field if it can
+/// be fixed safely.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Problem {
+    pub key: String,
+    pub message: String,
+    pub fix: Option<(String, json::JsonValue)>,
+}
+
+fn problem(key: &str, message: String) -> Problem {
+    Problem { key: key.to_string(), message, fix: None }
+}
+
+fn has_field(paper: &json::JsonValue, field: &str) -> bool {
+    field.split('|').any(|field| !paper[field].is_null())
+}
+
+/// Page ranges with a single hyphen, such as 12-34, use an en dash.
+fn fix_page_range(pages: &str) -> Option<String> {
+    let parts = pages.split('-').collect::<Vec<_>>();
+    let is_page = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_alphanumeric());
+    if parts.len() == 2 && parts.iter().all(|part| is_page(part)) {
+        return Some(format!("{}--{}", parts[0], parts[1]))
+    }
+    return None
+}
+
+/// Check a paper in the internal form against the rules of a dialect. Strict
+/// checking also reports fields not known for the entry type.
+pub fn lint_paper(key: &str, paper: &json::JsonValue, dialect: Dialect, strict: bool) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let (rules, converted) = match dialect {
+        Dialect::Bibtex => (&BIBTEX_RULES[..], paper.clone()),
+        Dialect::Biblatex => (&BIBLATEX_RULES[..], bibtex::to_biblatex(paper.clone())),
+    };
+    let entry_type = converted["entry_type"].as_str().unwrap_or("");
+    match rules.iter().find(|rule| rule.entry_type == entry_type) {
+        Some(rule) => {
+            for field in rule.required.iter().filter(|field| !has_field(&converted, field)) {
+                problems.push(problem(key, format!("@{} is missing {}", entry_type, field.replace('|', " or "))));
+            }
+            if strict {
+                for (field, _) in converted.entries() {
+                    let known = COMMON_FIELDS.contains(&field)
+                        || rule.required.iter().chain(rule.optional.iter())
+                            .any(|allowed| allowed.split('|').any(|allowed| allowed == field));
+                    if !known {
+                        problems.push(problem(key, format!("Non-standard field {} for @{}", field, entry_type)));
+                    }
+                }
+            }
+        },
+        None => problems.push(problem(key, format!("Unknown entry type @{}", entry_type))),
+    }
+
+    for (field, value) in paper.entries() {
+        let value = match value.as_str() {
+            Some(value) => value,
+            None => continue,
+        };
+        if value.trim().is_empty() {
+            problems.push(problem(key, format!("Empty field {}", field)));
+            continue
+        }
+        if value.trim() != value {
+            problems.push(Problem {
+                key: key.to_string(),
+                message: format!("Whitespace around {}", field),
+                fix: Some((field.to_string(), json::from(value.trim()))),
+            });
+        }
+    }
+    if let Some(year) = paper["year"].as_str() {
+        if year.trim().len() != 4 || !year.trim().chars().all(|c| c.is_ascii_digit()) {
+            problems.push(problem(key, format!("Year {} is not a four digit number", year)));
+        }
+    }
+    if let Some(month) = paper["month"].as_str() {
+        if !bibtex::MONTHS.contains(&month.trim()) {
+            problems.push(problem(key, format!("Unknown month {}", month)));
+        }
+    }
+    if let Some(fixed) = paper["pages"].as_str().and_then(|pages| fix_page_range(pages.trim())) {
+        problems.push(Problem {
+            key: key.to_string(),
+            message: format!("Page range {} uses a single hyphen", paper["pages"]),
+            fix: Some((String::from("pages"), json::from(fixed))),
+        });
+    }
+    return problems
+}
+
+/// Apply the fixes of the problems to a paper, in order.
+pub fn apply_fixes(paper: &mut json::JsonValue, problems: &[Problem]) {
+    for (field, value) in problems.iter().filter_map(|problem| problem.fix.as_ref()) {
+        paper[field.as_str()] = value.clone();
+    }
+}
+
+#[cfg(test)]
+fn messages(paper: json::JsonValue, dialect: Dialect, strict: bool) -> Vec<String> {
+    lint_paper("k", &paper, dialect, strict).into_iter().map(|problem| problem.message).collect()
+}
+
+#[test]
+fn missing_required_fields() {
+    let paper = json::object!{"entry_type": "article", "title": "T", "year": "2017"};
+    assert_eq!(messages(paper, Dialect::Bibtex, false), vec!["@article is missing author", "@article is missing journal"]);
+    let paper = json::object!{"entry_type": "book", "editor": "E", "title": "T", "publisher": "P", "year": "2017"};
+    assert!(messages(paper, Dialect::Bibtex, false).is_empty());
+}
+
+#[test]
+fn biblatex_rules() {
+    let paper = json::object!{"entry_type": "misc", "author": "A", "title": "T", "year": "2017", "url": "https://example.org"};
+    assert!(messages(paper.clone(), Dialect::Biblatex, false).is_empty());
+    let paper = json::object!{"entry_type": "techreport", "author": "A", "title": "T", "year": "2017"};
+    assert_eq!(messages(paper, Dialect::Biblatex, false), vec!["@report is missing institution"]);
+}
+
+#[test]
+fn field_values() {
+    let paper = json::object!{"entry_type": "misc", "year": "17", "month": "May", "note": " "};
+    assert_eq!(
+        messages(paper, Dialect::Bibtex, false),
+        vec!["Empty field note", "Year 17 is not a four digit number", "Unknown month May"]
+    );
+}
+
+#[test]
+fn unknown_types_and_fields() {
+    let paper = json::object!{"entry_type": "article", "author": "A", "title": "T", "journal": "J", "year": "2017", "colour": "red", "doi": "10.1/x"};
+    assert_eq!(messages(paper.clone(), Dialect::Bibtex, true), vec!["Non-standard field colour for @article"]);
+    assert!(messages(paper, Dialect::Bibtex, false).is_empty());
+    assert_eq!(messages(json::object!{"entry_type": "webpage"}, Dialect::Bibtex, false), vec!["Unknown entry type @webpage"]);
+}
+
+#[test]
+fn fix_pages_and_whitespace() {
+    let mut paper = json::object!{"entry_type": "misc", "title": " Title ", "pages": " 12-34 "};
+    let problems = lint_paper("k", &paper, Dialect::Bibtex, false);
+    assert_eq!(problems.len(), 3);
+    apply_fixes(&mut paper, &problems);
+    assert_eq!(paper, json::object!{"entry_type": "misc", "title": "Title", "pages": "12--34"});
+    assert!(lint_paper("k", &paper, Dialect::Bibtex, false).is_empty());
+    assert_eq!(fix_page_range("12--34"), None);
+    assert_eq!(fix_page_range("e1-e12"), Some(String::from("e1--e12")));
+}
@@ -1,10 +1,33 @@
+#![allow(clippy::needless_return)]
+
 mod app;
 mod commands;
-mod rofi_picker;
+mod picker;
+mod config;
+mod clipboard;
+mod tui;
+mod reading;
+mod timestamps;
+mod journal;
+mod versioning;
+mod storage;
+mod sqlite;
+mod lint;
+mod normalize;
+mod venues;
+mod site;
+mod stats;
 mod latex_decoder;
+mod latex_encoder;
 mod string_cleaner;
 mod filter;
 mod bibtex;
+mod ris;
+mod citation;
+mod template;
+mod sort;
+mod ranking;
+mod fulltext;
 
 fn main() {
     app::App::run()
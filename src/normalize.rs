@@ -0,0 +1,164 @@
+use crate::bibtex;
+use crate::string_cleaner;
+
+// Stages run on imported papers, in this order. Each can be turned on or off
+// under "normalizers" in the config, for example
+//
+//   "normalizers": { "protect_title": true, "doi": false }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Normalizer {
+    LowercaseFields,
+    CollapseWhitespace,
+    Doi,
+    Month,
+    ProtectTitle,
+}
+
+// Names in the config and whether the stage runs by default.
+const NORMALIZERS: [(&str, Normalizer, bool); 5] = [
+    ("lowercase_fields", Normalizer::LowercaseFields, true),
+    ("collapse_whitespace", Normalizer::CollapseWhitespace, true),
+    ("doi", Normalizer::Doi, true),
+    ("month", Normalizer::Month, true),
+    ("protect_title", Normalizer::ProtectTitle, false),
+];
+
+const DOI_PREFIXES: [&str; 5] = [
+    "https://doi.org/", "http://doi.org/", "https://dx.doi.org/", "http://dx.doi.org/", "doi:",
+];
+
+/// The stages enabled by the config, in pipeline order.
+pub fn from_config(config: &json::JsonValue) -> Vec<Normalizer> {
+    let settings = &config["normalizers"];
+    for (name, _) in settings.entries() {
+        if !NORMALIZERS.iter().any(|(known, _, _)| known == &name) {
+            panic!("Unknown normalizer in config: {}", name);
+        }
+    }
+    NORMALIZERS.iter()
+        .filter(|(name, _, default)| settings[*name].as_bool().unwrap_or(*default))
+        .map(|(_, normalizer, _)| *normalizer)
+        .collect()
+}
+
+fn lowercase_fields(paper: json::JsonValue) -> json::JsonValue {
+    let mut lowercased = json::object!{};
+    for (field, value) in paper.entries() {
+        let field = field.to_lowercase();
+        // A field also given in lowercase keeps its first value.
+        if lowercased[field.as_str()].is_null() {
+            lowercased[field.as_str()] = value.clone();
+        }
+    }
+    return lowercased
+}
+
+fn canonical_doi(doi: &str) -> String {
+    let doi = doi.trim();
+    let prefix = DOI_PREFIXES.iter().find(|prefix| doi.to_lowercase().starts_with(*prefix));
+    match prefix {
+        Some(prefix) => doi[prefix.len()..].trim().to_string(),
+        None => doi.to_string(),
+    }
+}
+
+/// Brace words with capitals past their first letter, such as acronyms and
+/// names like PageRank, so styles changing the case of titles keep them.
+fn protect_title(title: &str) -> String {
+    let mut depth = 0;
+    let mut words = Vec::new();
+    for word in title.split(' ') {
+        let protect = depth == 0
+            && !word.contains(['{', '}', '\\', '$'])
+            && word.chars().skip(1).any(char::is_uppercase);
+        depth += word.matches('{').count() as i32 - word.matches('}').count() as i32;
+        words.push(if protect { format!("{{{}}}", word) } else { word.to_string() });
+    }
+    return words.join(" ")
+}
+
+fn apply(normalizer: Normalizer, mut paper: json::JsonValue) -> json::JsonValue {
+    match normalizer {
+        Normalizer::LowercaseFields => return lowercase_fields(paper),
+        Normalizer::CollapseWhitespace => {
+            for (_, value) in paper.entries_mut() {
+                if let Some(text) = value.as_str() {
+                    *value = json::from(string_cleaner::remove_extra_whitespace(text));
+                }
+            }
+        },
+        Normalizer::Doi => {
+            if let Some(doi) = paper["doi"].as_str() {
+                paper["doi"] = json::from(canonical_doi(doi));
+            }
+        },
+        Normalizer::Month => {
+            if let Some(month) = paper["month"].as_str().and_then(bibtex::month_number) {
+                paper["month"] = json::from(bibtex::MONTHS[month - 1]);
+            }
+        },
+        Normalizer::ProtectTitle => {
+            if let Some(title) = paper["title"].as_str() {
+                paper["title"] = json::from(protect_title(title));
+            }
+        },
+    }
+    return paper
+}
+
+/// Run the stages on each paper of a selection.
+pub fn normalize(mut selection: json::JsonValue, normalizers: &[Normalizer]) -> json::JsonValue {
+    for (_, paper) in selection.entries_mut() {
+        *paper = normalizers.iter().fold(paper.take(), |paper, normalizer| apply(*normalizer, paper));
+    }
+    return selection
+}
+
+#[cfg(test)]
+fn all_normalizers() -> Vec<Normalizer> {
+    NORMALIZERS.iter().map(|(_, normalizer, _)| *normalizer).collect()
+}
+
+#[test]
+fn normalize_imported_paper() {
+    let selection = json::object!{"k": {
+        "entry_type": "article",
+        "Author": "Min Lyu and\n               Dong Su",
+        "title": "Private  PageRank on {VLDB} Data",
+        "doi": "https://doi.org/10.14778/3055330.3055331",
+        "month": "May",
+    }};
+    assert_eq!(normalize(selection, &all_normalizers()), json::object!{"k": {
+        "entry_type": "article",
+        "author": "Min Lyu and Dong Su",
+        "title": "Private {PageRank} on {VLDB} Data",
+        "doi": "10.14778/3055330.3055331",
+        "month": "may",
+    }});
+}
+
+#[test]
+fn protect_title_words() {
+    assert_eq!(protect_title("The SVT and iPhone"), "The {SVT} and {iPhone}");
+    assert_eq!(protect_title("{Sparse Vector} DPSGD"), "{Sparse Vector} {DPSGD}");
+    assert_eq!(protect_title("A {\\AA}ngstr{\\\"o}m Unit"), "A {\\AA}ngstr{\\\"o}m Unit");
+}
+
+#[test]
+fn canonical_dois() {
+    assert_eq!(canonical_doi("DOI:10.1/x"), "10.1/x");
+    assert_eq!(canonical_doi(" http://dx.doi.org/10.1/x "), "10.1/x");
+    assert_eq!(canonical_doi("10.1/x"), "10.1/x");
+}
+
+#[test]
+fn normalizers_from_config() {
+    assert_eq!(from_config(&json::object!{}), vec![
+        Normalizer::LowercaseFields, Normalizer::CollapseWhitespace, Normalizer::Doi, Normalizer::Month,
+    ]);
+    let config = json::object!{"normalizers": {"protect_title": true, "doi": false, "month": false}};
+    assert_eq!(from_config(&config), vec![
+        Normalizer::LowercaseFields, Normalizer::CollapseWhitespace, Normalizer::ProtectTitle,
+    ]);
+}
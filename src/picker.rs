@@ -0,0 +1,270 @@
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use crate::template::Template;
+
+pub const DEFAULT_FORMAT: &str = "{title|clean} | {author|lastnames|clean} | {year}";
+
+/// A line shown in the menu and the citation key it stands for.
+pub struct Entry {
+    pub key: String,
+    pub line: String,
+}
+
+/// The keys picked, and the index of the custom keybinding used to accept
+/// the pick, if any. Cancelling the menu picks nothing.
+#[derive(Debug, PartialEq)]
+pub struct Picked {
+    pub keys: Vec<String>,
+    pub keybinding: Option<usize>,
+}
+
+pub trait Picker {
+    /// Let the user pick some of the entries. Pickers that support custom
+    /// keybindings bind them in order.
+    fn pick(&self, entries: &[Entry], keybindings: &[String]) -> Picked;
+}
+
+pub struct Rofi;
+pub struct Fzf;
+pub struct Dmenu;
+pub struct Tty;
+
+fn run_menu(mut command: Command, input: &str, name: &str) -> (String, Option<i32>) {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn().unwrap_or_else(|_| panic!("Failed to execute {}", name));
+    let stdin = child.stdin.as_mut().unwrap_or_else(|| panic!("Failed to get {} stdin", name));
+    stdin.write_all(input.as_bytes()).unwrap_or_else(|_| panic!("Failed to write {} argument", name));
+    stdin.flush().unwrap_or_else(|_| panic!("Failed to flush {} arguments", name));
+
+    let output = child.wait_with_output().unwrap_or_else(|_| panic!("Failed to wait on {}", name));
+    return (String::from_utf8(output.stdout).unwrap(), output.status.code())
+}
+
+fn single_line(line: &str) -> String {
+    line.replace(['\t', '\n'], " ")
+}
+
+/// Keys of the output lines of a menu fed "line\tkey" entries.
+fn keys_after_tab(output: &str) -> Vec<String> {
+    output.lines()
+        .filter_map(|line| line.rsplit('\t').next())
+        .filter(|key| !key.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Rofi has the custom keybindings kb-custom-1 to kb-custom-19.
+const ROFI_KEYBINDINGS: usize = 19;
+
+// Rofi exits with 1 when cancelled and with 10 + N for custom keybinding N.
+// Other exit codes are not one of the `count` keybindings bound.
+fn rofi_keybinding(exit_code: Option<i32>, count: usize) -> Option<usize> {
+    match exit_code {
+        Some(code) if code >= 10 && ((code - 10) as usize) < count.min(ROFI_KEYBINDINGS) => Some((code - 10) as usize),
+        _ => None,
+    }
+}
+
+impl Picker for Rofi {
+    fn pick(&self, entries: &[Entry], keybindings: &[String]) -> Picked {
+        let input = entries.iter()
+            .map(|entry| format!("{}\t{}\n", single_line(&entry.line), entry.key))
+            .collect::<String>();
+        let mut command = Command::new("rofi");
+        command.arg("-dmenu")
+            .arg("-format")
+            .arg("s")
+            .arg("-display-columns").arg("1")
+            .arg("-display-column-separator").arg("\t")
+            .arg("-multi-select")
+            .arg("-i");
+        if keybindings.len() > ROFI_KEYBINDINGS {
+            eprintln!("Rofi supports {} custom keybindings, ignoring the others", ROFI_KEYBINDINGS);
+        }
+        for (index, keybinding) in keybindings.iter().take(ROFI_KEYBINDINGS).enumerate() {
+            command.arg(format!("-kb-custom-{}", index + 1)).arg(keybinding);
+        }
+        let (out_str, exit_code) = run_menu(command, &input, "rofi");
+        if exit_code == Some(1) {
+            return Picked { keys: Vec::new(), keybinding: None }
+        }
+        return Picked { keys: keys_after_tab(&out_str), keybinding: rofi_keybinding(exit_code, keybindings.len()) }
+    }
+}
+
+impl Picker for Fzf {
+    fn pick(&self, entries: &[Entry], _keybindings: &[String]) -> Picked {
+        let input = entries.iter()
+            .map(|entry| format!("{}\t{}\n", single_line(&entry.line), entry.key))
+            .collect::<String>();
+        let mut command = Command::new("fzf");
+        command.arg("--multi")
+            .arg("--delimiter").arg("\t")
+            .arg("--with-nth").arg("1");
+        let (out_str, _) = run_menu(command, &input, "fzf");
+        return Picked { keys: keys_after_tab(&out_str), keybinding: None }
+    }
+}
+
+// dmenu has no hidden columns, so the key is shown after the line to tell
+// apart papers with identical lines.
+fn dmenu_line(entry: &Entry) -> String {
+    format!("{} [{}]", single_line(&entry.line), entry.key)
+}
+
+impl Picker for Dmenu {
+    fn pick(&self, entries: &[Entry], _keybindings: &[String]) -> Picked {
+        let input = entries.iter().map(|entry| format!("{}\n", dmenu_line(entry))).collect::<String>();
+        let mut command = Command::new("dmenu");
+        command.arg("-i").arg("-l").arg("20");
+        let (out_str, _) = run_menu(command, &input, "dmenu");
+
+        let keys = out_str.lines()
+            .filter_map(|selected| entries.iter().find(|entry| dmenu_line(entry) == selected))
+            .map(|entry| entry.key.clone())
+            .collect();
+        return Picked { keys, keybinding: None }
+    }
+}
+
+/// Parse a selection such as "1 3, 5-7" of one-based line numbers.
+fn parse_numbers(input: &str, count: usize) -> Vec<usize> {
+    let mut indices = Vec::new();
+    for part in input.split(|c: char| c == ',' || c.is_whitespace()).filter(|part| !part.is_empty()) {
+        let (start, end) = match part.find('-') {
+            Some(dash) => (part[..dash].parse::<usize>(), part[dash + 1..].parse::<usize>()),
+            None => (part.parse::<usize>(), part.parse::<usize>()),
+        };
+        if let (Ok(start), Ok(end)) = (start, end) {
+            for number in start..=end {
+                if number >= 1 && number <= count && !indices.contains(&(number - 1)) {
+                    indices.push(number - 1);
+                }
+            }
+        }
+    }
+    return indices
+}
+
+impl Picker for Tty {
+    fn pick(&self, entries: &[Entry], _keybindings: &[String]) -> Picked {
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")
+            .expect("Failed to open terminal");
+        let mut output = tty.try_clone().expect("Failed to open terminal");
+        for (index, entry) in entries.iter().enumerate() {
+            writeln!(output, "{:>3}) {}", index + 1, single_line(&entry.line)).expect("Failed to write to terminal");
+        }
+        write!(output, "Pick papers (e.g. 1 3 5-7): ").expect("Failed to write to terminal");
+        output.flush().expect("Failed to write to terminal");
+
+        let mut input = String::new();
+        BufReader::new(tty).read_line(&mut input).expect("Failed to read from terminal");
+        let keys = parse_numbers(&input, entries.len()).into_iter()
+            .map(|index| entries[index].key.clone())
+            .collect();
+        return Picked { keys, keybinding: None }
+    }
+}
+
+fn in_path(program: &str) -> bool {
+    match std::env::var_os("PATH") {
+        Some(paths) => std::env::split_paths(&paths).any(|dir| dir.join(program).is_file()),
+        None => false,
+    }
+}
+
+fn graphical() -> bool {
+    std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some()
+}
+
+pub fn picker_by_name(name: &str) -> Box<dyn Picker> {
+    match name {
+        "rofi" => Box::new(Rofi),
+        "fzf" => Box::new(Fzf),
+        "dmenu" => Box::new(Dmenu),
+        "tty" => Box::new(Tty),
+        _ => panic!("Unknown picker: {}", name),
+    }
+}
+
+/// Pick the first available of rofi and dmenu on a graphical session, then
+/// fzf, falling back to the built-in terminal picker.
+pub fn detect_picker() -> Box<dyn Picker> {
+    if graphical() && in_path("rofi") {
+        return Box::new(Rofi)
+    }
+    if in_path("fzf") {
+        return Box::new(Fzf)
+    }
+    if graphical() && in_path("dmenu") {
+        return Box::new(Dmenu)
+    }
+    return Box::new(Tty)
+}
+
+/// Narrow the selection down to the papers picked, shown with the template.
+/// Also returns the index of the custom keybinding used, if any.
+pub fn pick(mut selection: json::JsonValue, picker: &dyn Picker, template: &Template, keybindings: &[String]) -> (json::JsonValue, Option<usize>) {
+    let entries = selection.entries()
+        .map(|(key, paper)| Entry { key: key.to_string(), line: template.render(key, paper) })
+        .collect::<Vec<_>>();
+
+    let picked = picker.pick(&entries, keybindings);
+    let to_remove = entries.iter().filter(|entry| !picked.keys.contains(&entry.key));
+    for entry in to_remove {
+        selection.remove(&entry.key);
+    }
+    return (selection, picked.keybinding);
+}
+
+#[test]
+fn parse_number_ranges() {
+    assert_eq!(parse_numbers("1 3, 5-7", 10), vec![0, 2, 4, 5, 6]);
+}
+
+#[test]
+fn parse_numbers_ignores_invalid() {
+    assert_eq!(parse_numbers("0 2 2 11 x 9-12", 10), vec![1, 8, 9]);
+    assert!(parse_numbers("\n", 10).is_empty());
+}
+
+#[test]
+fn keys_from_tab_separated_output() {
+    assert_eq!(keys_after_tab("A title | Lyu\tDBLP:a\nOther\tb\n"), vec!["DBLP:a", "b"]);
+    assert!(keys_after_tab("").is_empty());
+    assert!(keys_after_tab("\n").is_empty());
+}
+
+#[test]
+fn rofi_exit_codes() {
+    assert_eq!(rofi_keybinding(Some(0), 3), None);
+    assert_eq!(rofi_keybinding(Some(1), 3), None);
+    assert_eq!(rofi_keybinding(Some(10), 3), Some(0));
+    assert_eq!(rofi_keybinding(Some(12), 3), Some(2));
+    assert_eq!(rofi_keybinding(Some(13), 3), None);
+    assert_eq!(rofi_keybinding(Some(29), 30), None);
+    assert_eq!(rofi_keybinding(None, 3), None);
+}
+
+#[cfg(test)]
+struct PickSecond;
+
+#[cfg(test)]
+impl Picker for PickSecond {
+    fn pick(&self, entries: &[Entry], keybindings: &[String]) -> Picked {
+        Picked { keys: vec![entries[1].key.clone()], keybinding: keybindings.first().map(|_| 0) }
+    }
+}
+
+#[test]
+fn pick_same_titles_by_key() {
+    let selection = json::object!{"a": {"title": "Same"}, "b": {"title": "Same"}};
+    let template = "{title}".parse::<Template>().unwrap();
+    let (picked, keybinding) = pick(selection, &PickSecond, &template, &[String::from("Alt+o")]);
+    assert!(picked.has_key("b"));
+    assert_eq!(picked.len(), 1);
+    assert_eq!(keybinding, Some(0));
+}
@@ -0,0 +1,29 @@
+// Okapi BM25 with the usual parameters.
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+
+/// BM25 score contribution of one query term in one document. The term
+/// frequency may be fractional for fuzzy matches.
+pub fn bm25(tf: f64, doc_length: usize, average_length: f64, doc_count: usize, total_docs: usize) -> f64 {
+    if tf <= 0.0 || total_docs == 0 {
+        return 0.0
+    }
+    let idf = ((total_docs as f64 - doc_count as f64 + 0.5) / (doc_count as f64 + 0.5) + 1.0).ln();
+    let length_ratio = if average_length > 0.0 { doc_length as f64 / average_length } else { 1.0 };
+    return idf * tf * (K1 + 1.0) / (tf + K1 * (1.0 - B + B * length_ratio))
+}
+
+#[test]
+fn bm25_prefers_rare_terms() {
+    assert!(bm25(1.0, 10, 10.0, 1, 100) > bm25(1.0, 10, 10.0, 50, 100));
+}
+
+#[test]
+fn bm25_prefers_shorter_documents() {
+    assert!(bm25(1.0, 5, 10.0, 1, 100) > bm25(1.0, 50, 10.0, 1, 100));
+}
+
+#[test]
+fn bm25_zero_without_term() {
+    assert_eq!(bm25(0.0, 10, 10.0, 1, 100), 0.0);
+}
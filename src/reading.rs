@@ -0,0 +1,85 @@
+use crate::string_cleaner;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+pub const STATUSES: [&str; 4] = ["unread", "reading", "read", "skimmed"];
+pub const RATINGS: [&str; 5] = ["1", "2", "3", "4", "5"];
+
+/// Check the value of the reading status and rating fields. Other fields
+/// accept any value.
+pub fn validate(field: &str, value: &str) -> Result<(), String> {
+    match field {
+        "status" if !STATUSES.contains(&value) => {
+            Err(format!("Invalid status {:?}, expected one of {}", value, STATUSES.join(", ")))
+        },
+        "rating" if !RATINGS.contains(&value) => {
+            Err(format!("Invalid rating {:?}, expected 1 to 5", value))
+        },
+        _ => Ok(()),
+    }
+}
+
+/// Value of a field, with papers without a status counting as unread.
+pub fn field<'a>(paper: &'a json::JsonValue, name: &str) -> Cow<'a, json::JsonValue> {
+    if name == "status" && paper[name].is_null() {
+        return Cow::Owned(json::from(STATUSES[0]))
+    }
+    return Cow::Borrowed(&paper[name])
+}
+
+/// Markdown note files of the papers of the library, named like their files
+/// in the directory storage so that keys such as `a:b` and `a/b` get one each.
+pub fn note_files(notes_dir: &Path, db: &json::JsonValue) -> HashMap<String, PathBuf> {
+    return string_cleaner::unique_file_stems(db.entries().map(|(key, _)| key)).into_iter()
+        .map(|(key, stem)| (key, notes_dir.join(format!("{}.md", stem))))
+        .collect()
+}
+
+/// Text of the Markdown notes of the papers of the library that have one.
+pub fn load_notes(notes_dir: &Path, db: &json::JsonValue) -> HashMap<String, String> {
+    return note_files(notes_dir, db).into_iter()
+        .filter_map(|(key, note)| std::fs::read_to_string(note).ok().map(|text| (key, text)))
+        .collect()
+}
+
+/// Open the Markdown note of a paper in $EDITOR, starting it with the title.
+pub fn edit_note(note: &Path, key: &str, paper: &json::JsonValue) {
+    if let Some(notes_dir) = note.parent() {
+        std::fs::create_dir_all(notes_dir).expect("Could not create notes directory");
+    }
+    if !note.exists() {
+        let title = string_cleaner::clean_string(paper["title"].as_str().unwrap_or(key));
+        std::fs::write(note, format!("# {}\n\n", title)).expect("Could not create note");
+    }
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| String::from("vi"));
+    std::process::Command::new(&editor)
+        .arg(note)
+        .status()
+        .expect("Failed to open editor");
+}
+
+#[test]
+fn distinct_notes_for_similar_keys() {
+    let db = json::object!{"a:b": {}, "a/b": {}, "c": {}};
+    let notes = note_files(Path::new("notes"), &db);
+    assert_eq!(notes["a/b"], Path::new("notes/a_b.md"));
+    assert_eq!(notes["a:b"], Path::new("notes/a_b_2.md"));
+    assert_eq!(notes["c"], Path::new("notes/c.md"));
+}
+
+#[test]
+fn validate_status_and_rating() {
+    assert!(validate("status", "read").is_ok());
+    assert!(validate("status", "done").is_err());
+    assert!(validate("rating", "5").is_ok());
+    assert!(validate("rating", "6").is_err());
+    assert!(validate("title", "anything").is_ok());
+}
+
+#[test]
+fn missing_status_is_unread() {
+    let paper = json::object!{"title": "T"};
+    assert_eq!(*field(&paper, "status"), "unread");
+    assert!(field(&paper, "rating").is_null());
+}
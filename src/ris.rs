@@ -0,0 +1,400 @@
+use crate::bibtex;
+use crate::string_cleaner;
+
+// RIS tags and the BibTeX fields they map to:
+//
+//   TY          entry_type (see ENTRY_TYPES)
+//   ID          citation key
+//   AU, A1      author (one tag per name, joined with " and ")
+//   A2, ED      editor (one tag per name, joined with " and ")
+//   TI, T1      title
+//   T2          journal for JOUR, booktitle otherwise
+//   JO, JF, JA  journal
+//   PY, Y1, DA  year (the first four characters)
+//   VL          volume
+//   IS          number
+//   SP, EP      pages ("SP--EP")
+//   PB          publisher
+//   CY          address
+//   SN          isbn for books, issn otherwise
+//   DO          doi
+//   UR          url
+//   L1          file (on import, private fields are not exported)
+//   AB          abstract
+//   KW          keywords (one tag per keyword, joined with ", ")
+//   N1          note
+//   LA          language
+//
+// Any other tag is ignored on import.
+
+const ENTRY_TYPES: [(&str, &str); 11] = [
+    ("JOUR", "article"),
+    ("BOOK", "book"),
+    ("EDBOOK", "book"),
+    ("CHAP", "incollection"),
+    ("CONF", "inproceedings"),
+    ("CPAPER", "inproceedings"),
+    ("THES", "phdthesis"),
+    ("RPRT", "techreport"),
+    ("UNPB", "unpublished"),
+    ("GEN", "misc"),
+    ("ELEC", "misc"),
+];
+
+const FIELD_TAGS: [(&str, &str); 12] = [
+    ("title", "TI"),
+    ("year", "PY"),
+    ("volume", "VL"),
+    ("number", "IS"),
+    ("publisher", "PB"),
+    ("address", "CY"),
+    ("doi", "DO"),
+    ("url", "UR"),
+    ("file", "L1"),
+    ("abstract", "AB"),
+    ("note", "N1"),
+    ("language", "LA"),
+];
+
+// Fields that are copied verbatim on export instead of being LaTeX decoded.
+const RAW_FIELDS: [&str; 3] = ["doi", "url", "file"];
+
+fn entry_type_from_ris(ris_type: &str) -> &'static str {
+    ENTRY_TYPES.iter()
+        .find(|(ris, _)| *ris == ris_type)
+        .map(|(_, bib)| *bib)
+        .unwrap_or("misc")
+}
+
+fn entry_type_to_ris(entry_type: &str) -> &'static str {
+    match entry_type {
+        "inbook" => "CHAP",
+        "proceedings" => "CONF",
+        "mastersthesis" => "THES",
+        "online" => "ELEC",
+        _ => ENTRY_TYPES.iter()
+            .find(|(_, bib)| *bib == entry_type)
+            .map(|(ris, _)| *ris)
+            .unwrap_or("GEN"),
+    }
+}
+
+fn parse_line(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim_end();
+    if line.get(2..5) != Some("  -") {
+        return None
+    }
+    let tag = &line[..2];
+    let value = line[5..].trim();
+    return Some((tag, value))
+}
+
+fn push_joined(paper: &mut json::JsonValue, field: &str, value: &str, separator: &str) {
+    if paper[field].is_null() {
+        paper[field] = json::from(value);
+    } else {
+        let joined = format!("{}{}{}", paper[field], separator, value);
+        paper[field] = json::from(joined);
+    }
+}
+
+fn set_field(paper: &mut json::JsonValue, ris_type: &str, tag: &str, value: &str) {
+    match tag {
+        "AU" | "A1" => push_joined(paper, "author", value, " and "),
+        "A2" | "ED" => push_joined(paper, "editor", value, " and "),
+        "KW" => push_joined(paper, "keywords", value, ", "),
+        "TI" | "T1" => paper["title"] = json::from(value),
+        "T2" if ris_type != "JOUR" => paper["booktitle"] = json::from(value),
+        "T2" | "JO" | "JF" | "JA" if paper["journal"].is_null() => paper["journal"] = json::from(value),
+        "PY" | "Y1" | "DA" if paper["year"].is_null() && value.chars().count() >= 4 => {
+            paper["year"] = json::from(value.chars().take(4).collect::<String>())
+        },
+        "VL" => paper["volume"] = json::from(value),
+        "IS" => paper["number"] = json::from(value),
+        "SP" => paper["start_page"] = json::from(value),
+        "EP" => paper["end_page"] = json::from(value),
+        "PB" => paper["publisher"] = json::from(value),
+        "CY" => paper["address"] = json::from(value),
+        "SN" if ris_type == "BOOK" || ris_type == "EDBOOK" => paper["isbn"] = json::from(value),
+        "SN" => paper["issn"] = json::from(value),
+        "DO" => paper["doi"] = json::from(value),
+        "UR" => paper["url"] = json::from(value),
+        "L1" => paper["file"] = json::from(value),
+        "AB" => paper["abstract"] = json::from(value),
+        "N1" => paper["note"] = json::from(value),
+        "LA" => paper["language"] = json::from(value),
+        _ => (),
+    }
+}
+
+fn finish_pages(paper: &mut json::JsonValue) {
+    let start = paper.remove("start_page");
+    let end = paper.remove("end_page");
+    let pages = match (start.as_str(), end.as_str()) {
+        (Some(start), Some(end)) => format!("{}--{}", start, end),
+        (Some(start), None) => start.to_string(),
+        (None, Some(end)) => end.to_string(),
+        (None, None) => return,
+    };
+    paper["pages"] = json::from(pages);
+}
+
+fn generate_key(paper: &json::JsonValue, selection: &json::JsonValue) -> String {
+    let last_name = paper["author"].as_str()
+        .and_then(|authors| bibtex::split_authors(authors).into_iter().next())
+        .map(|author| bibtex::last_name(&author))
+        .unwrap_or_else(|| String::from("anon"));
+    let base = format!("{}{}", string_cleaner::clean_and_decode(&last_name).replace(' ', ""), paper["year"].as_str().unwrap_or(""));
+    let mut key = base.clone();
+    let mut suffix = 0;
+    while selection.has_key(&key) {
+        key = format!("{}{}", base, letters(suffix));
+        suffix += 1;
+    }
+    return key
+}
+
+/// The suffixes a, b, …, z, aa, ab, … in order.
+fn letters(index: usize) -> String {
+    let mut letters = String::new();
+    let mut rest = index + 1;
+    while rest > 0 {
+        letters.insert(0, (b'a' + ((rest - 1) % 26) as u8) as char);
+        rest = (rest - 1) / 26;
+    }
+    return letters
+}
+
+pub fn parse_ris(ris_string: &str) -> json::JsonValue {
+    let mut new_selection = json::object!{};
+    let mut paper = json::JsonValue::Null;
+    let mut ris_type = String::new();
+    let mut key: Option<String> = None;
+
+    for (tag, value) in ris_string.lines().filter_map(parse_line) {
+        match tag {
+            "TY" => {
+                ris_type = value.to_string();
+                paper = json::object!{};
+                paper["entry_type"] = json::from(entry_type_from_ris(value));
+                key = None;
+            },
+            "ER" => {
+                if paper.is_null() {
+                    continue
+                }
+                finish_pages(&mut paper);
+                let citation_key = key.take()
+                    .unwrap_or_else(|| generate_key(&paper, &new_selection));
+                new_selection[citation_key] = paper.take();
+            },
+            "ID" => key = Some(value.to_string()),
+            _ if paper.is_null() => (),
+            _ => set_field(&mut paper, &ris_type, tag, value),
+        }
+    }
+    return new_selection
+}
+
+fn push_tag(result: &mut String, tag: &str, value: &str) {
+    result.push_str(&format!("{}  - {}\n", tag, value));
+}
+
+fn export_value(field: &str, value: &json::JsonValue) -> String {
+    let value = value.to_string();
+    if RAW_FIELDS.contains(&field) {
+        return value
+    }
+    return string_cleaner::clean_string(&value)
+}
+
+pub fn generate_ris(selection: json::JsonValue) -> String {
+    let mut result = String::new();
+    for (citation_key, paper) in selection.entries() {
+        let entry_type = paper["entry_type"].as_str().unwrap_or("misc");
+        let ris_type = entry_type_to_ris(entry_type);
+        push_tag(&mut result, "TY", ris_type);
+        push_tag(&mut result, "ID", citation_key);
+        for (field, tag) in &[("author", "AU"), ("editor", "ED")] {
+            if let Some(names) = paper[*field].as_str() {
+                for name in bibtex::split_authors(names) {
+                    push_tag(&mut result, tag, &string_cleaner::clean_string(&name));
+                }
+            }
+        }
+        if let Some(keywords) = paper["keywords"].as_str() {
+            for keyword in keywords.split(',').map(str::trim).filter(|k| !k.is_empty()) {
+                push_tag(&mut result, "KW", &string_cleaner::clean_string(keyword));
+            }
+        }
+        for (field, tag) in FIELD_TAGS.iter().filter(|(field, _)| !bibtex::PRIVATE_FIELDS.contains(field)) {
+            if !paper[*field].is_null() {
+                push_tag(&mut result, tag, &export_value(field, &paper[*field]));
+            }
+        }
+        if !paper["journal"].is_null() {
+            let tag = if ris_type == "JOUR" { "JO" } else { "T2" };
+            push_tag(&mut result, tag, &export_value("journal", &paper["journal"]));
+        }
+        if !paper["booktitle"].is_null() {
+            push_tag(&mut result, "T2", &export_value("booktitle", &paper["booktitle"]));
+        }
+        if let Some(pages) = paper["pages"].as_str() {
+            let mut split = pages.split('-').map(str::trim).filter(|p| !p.is_empty());
+            if let Some(start) = split.next() {
+                push_tag(&mut result, "SP", start);
+            }
+            if let Some(end) = split.next() {
+                push_tag(&mut result, "EP", end);
+            }
+        }
+        for (field, tag) in &[("isbn", "SN"), ("issn", "SN")] {
+            if !paper[*field].is_null() {
+                push_tag(&mut result, tag, &export_value(field, &paper[*field]));
+            }
+        }
+        result.push_str("ER  - \n\n");
+    }
+    return result;
+}
+
+#[cfg(test)]
+const SAMPLE_RIS: &str = "TY  - JOUR
+AU  - Lyu, Min
+AU  - Su, Dong
+AU  - Li, Ninghui
+TI  - Understanding the Sparse Vector Technique for Differential Privacy
+JO  - Proc. VLDB Endow.
+VL  - 10
+IS  - 6
+SP  - 637
+EP  - 648
+PY  - 2017///
+DO  - 10.14778/3055330.3055331
+UR  - http://www.vldb.org/pvldb/vol10/p637-lyu.pdf
+KW  - privacy
+KW  - sparse vector
+ER  -
+";
+
+#[test]
+fn parse_journal_article() {
+    let selection = parse_ris(SAMPLE_RIS);
+    let paper = &selection["Lyu2017"];
+    assert_eq!(paper["entry_type"], "article");
+    assert_eq!(paper["author"], "Lyu, Min and Su, Dong and Li, Ninghui");
+    assert_eq!(paper["title"], "Understanding the Sparse Vector Technique for Differential Privacy");
+    assert_eq!(paper["journal"], "Proc. VLDB Endow.");
+    assert_eq!(paper["volume"], "10");
+    assert_eq!(paper["number"], "6");
+    assert_eq!(paper["pages"], "637--648");
+    assert_eq!(paper["year"], "2017");
+    assert_eq!(paper["doi"], "10.14778/3055330.3055331");
+    assert_eq!(paper["keywords"], "privacy, sparse vector");
+}
+
+#[test]
+fn parse_uses_id_as_key() {
+    let selection = parse_ris("TY  - GEN\nID  - mykey\nTI  - Title\nER  - \n");
+    assert!(selection.has_key("mykey"));
+    assert_eq!(selection["mykey"]["entry_type"], "misc");
+}
+
+#[test]
+fn parse_generates_distinct_keys() {
+    let ris = "TY  - JOUR\nAU  - Smith, A.\nPY  - 2020\nER  - \nTY  - JOUR\nAU  - Smith, B.\nPY  - 2020\nER  - \n";
+    let selection = parse_ris(ris);
+    assert!(selection.has_key("Smith2020"));
+    assert!(selection.has_key("Smith2020a"));
+}
+
+#[test]
+fn parse_generates_keys_past_the_alphabet() {
+    let ris = "TY  - JOUR\nAU  - Smith, A.\nPY  - 2020\nER  - \n".repeat(300);
+    let selection = parse_ris(&ris);
+    assert_eq!(selection.len(), 300);
+    assert!(selection.has_key("Smith2020z"));
+    assert!(selection.has_key("Smith2020aa"));
+    assert_eq!(letters(0), "a");
+    assert_eq!(letters(27), "ab");
+    assert_eq!(letters(26 + 26 * 26), "aaa");
+}
+
+#[test]
+fn parse_conference_secondary_title() {
+    let selection = parse_ris("TY  - CONF\nID  - k\nT2  - Proc. of Something\nER  - \n");
+    assert_eq!(selection["k"]["entry_type"], "inproceedings");
+    assert_eq!(selection["k"]["booktitle"], "Proc. of Something");
+}
+
+#[test]
+fn parse_ignores_unknown_tags_and_junk() {
+    let selection = parse_ris("junk\nTY  - BOOK\nID  - k\nZZ  - x\nSN  - 123\nER  - \n");
+    assert_eq!(selection["k"]["entry_type"], "book");
+    assert_eq!(selection["k"]["isbn"], "123");
+    assert!(selection["k"]["ZZ"].is_null());
+}
+
+#[test]
+fn generate_round_trip() {
+    let selection = parse_ris(SAMPLE_RIS);
+    let generated = generate_ris(selection.clone());
+    assert!(generated.starts_with("TY  - JOUR\nID  - Lyu2017\n"));
+    assert!(generated.contains("SP  - 637\nEP  - 648\n"));
+    assert_eq!(parse_ris(&generated), selection);
+}
+
+#[test]
+fn generate_skips_private_fields() {
+    let selection = json::object!{"k": {
+        "entry_type": "article",
+        "title": "Title",
+        "file": "/home/me/papers/k.pdf",
+        "status": "read",
+        "rating": 5,
+        "tags": ["dp"],
+    }};
+    let generated = generate_ris(selection);
+    assert!(generated.contains("TI  - Title\n"));
+    assert!(!generated.contains("L1"));
+    assert!(!generated.contains("k.pdf"));
+    assert!(!generated.contains("read"));
+    assert!(!generated.contains("dp"));
+}
+
+#[test]
+fn generate_decodes_latex() {
+    let mut selection = json::object!{};
+    selection["k"] = json::object!{
+        "entry_type": "inproceedings",
+        "author": "Ossi R{\\\"a}is{\\\"a} and\n   Antti Honkela",
+        "booktitle": "Proc. {AISTATS}",
+    };
+    let generated = generate_ris(selection);
+    assert!(generated.contains("TY  - CONF\n"));
+    assert!(generated.contains("AU  - Ossi Räisä\nAU  - Antti Honkela\n"));
+    assert!(generated.contains("T2  - Proc. AISTATS\n"));
+}
+
+#[test]
+fn parse_non_ascii_lines() {
+    let selection = parse_ris("TYéé
+TY  - JOUR
+AU  - Smith, A.
+PY  - 20é
+DA  - 2021/01/01
+ER  - 
+TY  - JOUR
+AU  - Doe, J.
+PY  - ééé
+ER  - 
+");
+    assert_eq!(selection["Smith2021"]["year"], "2021");
+    assert!(selection.has_key("Doe"));
+}
+
+#[test]
+fn generate_misc_as_gen() {
+    assert_eq!(entry_type_to_ris("misc"), "GEN");
+    assert_eq!(entry_type_to_ris("online"), "ELEC");
+}
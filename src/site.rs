@@ -0,0 +1,288 @@
+use crate::bibtex::{self, Dialect};
+use crate::citation::{self, escape_html, Markup, Style};
+use crate::sort;
+use crate::string_cleaner;
+use crate::template::Template;
+use std::collections::HashMap;
+use std::path::Path;
+use std::str::FromStr;
+
+// A static publication list: index.html with the selection grouped by year
+// or entry type, style.css beside it and the PDFs of the papers under pdf/.
+// The page, each group and each entry come from templates, which can be
+// overridden by page.html, group.html, entry.html and style.css in a
+// template directory. Besides the fields of the paper, entries get
+//
+//   {anchor}    the citation key usable as an HTML id
+//   {citation}  the formatted reference
+//   {links}     links to the DOI, URL and PDF
+//   {bibtex}    the BibTeX of the paper, escaped for HTML
+//
+// and groups get {anchor}, {heading}, {count} and {entries}. The page gets
+// {title} and {groups}.
+
+const PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<link rel="stylesheet" href="style.css">
+<link rel="stylesheet" href="https://cdn.jsdelivr.net/npm/katex@0.16/dist/katex.min.css">
+<script defer src="https://cdn.jsdelivr.net/npm/katex@0.16/dist/katex.min.js"></script>
+<script defer src="https://cdn.jsdelivr.net/npm/katex@0.16/dist/contrib/auto-render.min.js" onload="renderMathInElement(document.body)"></script>
+</head>
+<body>
+<h1>{title}</h1>
+{groups}</body>
+</html>
+"#;
+
+const GROUP: &str = r#"<h2 id="{anchor}">{heading}</h2>
+<ul class="publications">
+{entries}</ul>
+"#;
+
+const ENTRY: &str = r#"<li id="{anchor}">{citation}
+<span class="links">{links}</span>
+<details class="bibtex"><summary>BibTeX</summary><pre>{bibtex}</pre></details>
+</li>
+"#;
+
+const STYLE: &str = "body { max-width: 50em; margin: auto; padding: 1em; font-family: sans-serif; line-height: 1.4; }
+.publications li { margin-bottom: 1em; }
+.links a { margin-right: 0.5em; font-size: 0.9em; }
+details.bibtex { display: inline-block; position: relative; font-size: 0.9em; }
+details.bibtex summary { cursor: pointer; color: #06c; }
+details.bibtex[open] pre { position: absolute; z-index: 1; margin: 0.3em 0; padding: 0.5em; background: white; border: 1px solid #ccc; box-shadow: 0 2px 6px #0003; white-space: pre-wrap; width: 40em; }
+";
+
+// Headings of entry types, in the order their groups are listed.
+const TYPE_HEADINGS: [(&str, &str); 10] = [
+    ("article", "Journal Articles"),
+    ("inproceedings", "Conference Papers"),
+    ("book", "Books"),
+    ("incollection", "Book Chapters"),
+    ("inbook", "Book Chapters"),
+    ("phdthesis", "Theses"),
+    ("mastersthesis", "Theses"),
+    ("techreport", "Technical Reports"),
+    ("unpublished", "Preprints"),
+    ("misc", "Other"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    Year,
+    Type,
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<GroupBy, String> {
+        match s {
+            "year" => Ok(GroupBy::Year),
+            "type" => Ok(GroupBy::Type),
+            _ => Err(format!("Unknown grouping: {}", s)),
+        }
+    }
+}
+
+pub struct Templates {
+    pub page: Template,
+    pub group: Template,
+    pub entry: Template,
+    pub style: String,
+}
+
+fn parse_template(template: &str, name: &str) -> Template {
+    Template::parse(template).unwrap_or_else(|error| panic!("Invalid site template {}: {}", name, error))
+}
+
+impl Templates {
+    /// The default templates with those found in a directory replacing them.
+    pub fn load(dir: Option<&Path>) -> Templates {
+        let read = |name: &str, default: &str| match dir.map(|dir| dir.join(name)).filter(|path| path.exists()) {
+            Some(path) => std::fs::read_to_string(&path).expect("Could not read site template"),
+            None => default.to_string(),
+        };
+        Templates {
+            page: parse_template(&read("page.html", PAGE), "page.html"),
+            group: parse_template(&read("group.html", GROUP), "group.html"),
+            entry: parse_template(&read("entry.html", ENTRY), "entry.html"),
+            style: read("style.css", STYLE),
+        }
+    }
+}
+
+/// Where the PDFs of the papers are published, relative to the page, by
+/// citation key.
+pub fn pdf_paths(selection: &json::JsonValue) -> HashMap<String, String> {
+    let mut stems = string_cleaner::unique_file_stems(selection.entries().map(|(key, _)| key));
+    for stem in stems.values_mut() {
+        *stem = format!("pdf/{}.pdf", stem);
+    }
+    return stems
+}
+
+/// The heading of the group of a paper. Several entry types can share a
+/// heading, as theses do.
+fn group_heading(paper: &json::JsonValue, group_by: GroupBy) -> String {
+    match group_by {
+        GroupBy::Year => {
+            // Years imported from JSON can be numbers.
+            let year = if paper["year"].is_null() { String::new() } else { string_cleaner::clean_string(&paper["year"].to_string()) };
+            if year.is_empty() { String::from("Undated") } else { year }
+        },
+        GroupBy::Type => {
+            let entry_type = paper["entry_type"].as_str().unwrap_or("");
+            TYPE_HEADINGS.iter()
+                .find(|(known, _)| *known == entry_type)
+                .map(|(_, heading)| heading.to_string())
+                .unwrap_or_else(|| entry_type.to_string())
+        },
+    }
+}
+
+/// Papers in groups with their headings, newest years first or in the
+/// order of TYPE_HEADINGS. Papers keep the order of the selection, newest
+/// first, within their group.
+fn groups(selection: &json::JsonValue, group_by: GroupBy) -> Vec<(String, json::JsonValue)> {
+    let sorted = sort::sort_selection(selection.clone(), "year", true);
+    let mut groups: Vec<(String, json::JsonValue)> = Vec::new();
+    for (key, paper) in sorted.entries() {
+        let heading = group_heading(paper, group_by);
+        match groups.iter_mut().find(|(group, _)| *group == heading) {
+            Some((_, papers)) => papers[key] = paper.clone(),
+            None => {
+                let mut papers = json::object!{};
+                papers[key] = paper.clone();
+                groups.push((heading, papers));
+            },
+        }
+    }
+    if group_by == GroupBy::Type {
+        let order = |heading: &str| TYPE_HEADINGS.iter().position(|(_, known)| *known == heading).unwrap_or(TYPE_HEADINGS.len());
+        groups.sort_by(|(a, _), (b, _)| order(a).cmp(&order(b)).then_with(|| a.cmp(b)));
+    }
+    return groups
+}
+
+fn links(paper: &json::JsonValue, pdf_path: Option<&str>) -> String {
+    let mut links = Vec::new();
+    if let Some(doi) = paper["doi"].as_str() {
+        links.push(format!("<a class=\"doi\" href=\"https://doi.org/{}\">DOI</a>", escape_html(doi)));
+    }
+    if let Some(url) = paper["url"].as_str() {
+        links.push(format!("<a class=\"url\" href=\"{}\">URL</a>", escape_html(url)));
+    }
+    if let Some(pdf_path) = pdf_path {
+        links.push(format!("<a class=\"pdf\" href=\"{}\">PDF</a>", escape_html(pdf_path)));
+    }
+    return links.join("\n")
+}
+
+fn public_bibtex(key: &str, paper: &json::JsonValue) -> String {
+    let mut selection = json::object!{};
+    selection[key] = paper.clone();
+    return bibtex::generate_bibtex(selection, Dialect::Bibtex)
+}
+
+/// The page for a selection. `has_pdf` tells whether a paper's PDF is
+/// published beside the page.
+pub fn render_site(
+    selection: &json::JsonValue,
+    templates: &Templates,
+    group_by: GroupBy,
+    style: Style,
+    title: &str,
+    has_pdf: &dyn Fn(&str) -> bool,
+) -> String {
+    let anchors = string_cleaner::unique_file_stems(selection.entries().map(|(key, _)| key));
+    let pdf_paths = pdf_paths(selection);
+    let mut rendered_groups = String::new();
+    for (heading, papers) in groups(selection, group_by) {
+        let mut entries = String::new();
+        for (key, paper) in papers.entries() {
+            let mut fields = paper.clone();
+            fields["anchor"] = json::from(anchors[key].as_str());
+            fields["citation"] = json::from(citation::format_reference(paper, style, Markup::Html));
+            let pdf_path = Some(pdf_paths[key].as_str()).filter(|_| has_pdf(key));
+            fields["links"] = json::from(links(paper, pdf_path));
+            fields["bibtex"] = json::from(escape_html(&public_bibtex(key, paper)));
+            entries.push_str(&templates.entry.render(key, &fields));
+        }
+        let group = json::object!{
+            "anchor": string_cleaner::key_file_stem(&heading.to_lowercase()),
+            "heading": escape_html(&heading),
+            "count": papers.len(),
+            "entries": entries,
+        };
+        rendered_groups.push_str(&templates.group.render("", &group));
+    }
+    let page = json::object!{"title": escape_html(title), "groups": rendered_groups};
+    return templates.page.render("", &page)
+}
+
+#[cfg(test)]
+fn sample_selection() -> json::JsonValue {
+    json::object!{
+        "old": {"entry_type": "article", "title": "Old", "year": "2015", "journal": "J", "file": "/home/me/old.pdf"},
+        "thesis": {"entry_type": "phdthesis", "title": "Thesis", "year": "2019", "school": "S"},
+        "new": {"entry_type": "inproceedings", "title": "\\emph{New}", "year": "2020", "doi": "10.1/x", "tags": ["mine"]},
+        "preprint": {"entry_type": "misc", "title": "Draft"},
+    }
+}
+
+#[cfg(test)]
+fn headings(selection: &json::JsonValue, group_by: GroupBy) -> Vec<String> {
+    groups(selection, group_by).into_iter().map(|(heading, _)| heading).collect()
+}
+
+#[test]
+fn group_by_year_and_type() {
+    let selection = sample_selection();
+    assert_eq!(headings(&selection, GroupBy::Year), vec!["2020", "2019", "2015", "Undated"]);
+    assert_eq!(headings(&selection, GroupBy::Type), vec!["Journal Articles", "Conference Papers", "Theses", "Other"]);
+}
+
+#[test]
+fn render_entries() {
+    let page = render_site(&sample_selection(), &Templates::load(None), GroupBy::Year, Style::Apa, "Our <Group>", &|key| key == "old");
+    assert!(page.contains("<title>Our &lt;Group&gt;</title>"));
+    assert!(page.contains("<h2 id=\"2020\">2020</h2>"));
+    assert!(page.contains("<li id=\"new\">(2020). <em>New</em>."));
+    assert!(page.contains("<a class=\"doi\" href=\"https://doi.org/10.1/x\">DOI</a>"));
+    assert!(page.contains("<a class=\"pdf\" href=\"pdf/old.pdf\">PDF</a>"));
+    assert!(page.contains("<pre>@inproceedings{new,\n    title = {\\emph{New}},"));
+    assert!(!page.contains("/home/me/old.pdf"));
+    assert!(!page.contains("mine"));
+}
+
+#[test]
+fn render_colliding_keys_and_numeric_years() {
+    let selection = json::object!{
+        "a:b": {"entry_type": "misc", "title": "One", "year": 2021},
+        "a/b": {"entry_type": "misc", "title": "Two", "year": "2021"},
+    };
+    assert_eq!(headings(&selection, GroupBy::Year), vec!["2021"]);
+    let page = render_site(&selection, &Templates::load(None), GroupBy::Year, Style::Apa, "", &|_| true);
+    assert!(page.contains("<li id=\"a_b\">(2021). Two."));
+    assert!(page.contains("<li id=\"a_b_2\">"));
+    assert!(page.contains("href=\"pdf/a_b.pdf\"") && page.contains("href=\"pdf/a_b_2.pdf\""));
+}
+
+#[test]
+fn render_with_template_overrides() {
+    let templates = Templates {
+        page: parse_template("{groups}", "page.html"),
+        group: parse_template("# {heading} ({count})\n{entries}", "group.html"),
+        entry: parse_template("- {key}: {title|markdown}\n", "entry.html"),
+        style: String::new(),
+    };
+    let page = render_site(&sample_selection(), &templates, GroupBy::Type, Style::Apa, "", &|_| false);
+    assert_eq!(
+        page,
+        "# Journal Articles (1)\n- old: Old\n# Conference Papers (1)\n- new: *New*\n# Theses (1)\n- thesis: Thesis\n# Other (1)\n- preprint: Draft\n"
+    );
+}
@@ -0,0 +1,125 @@
+use crate::bibtex;
+use crate::reading;
+use crate::string_cleaner;
+use std::cmp::Ordering;
+
+const NUMERIC_FIELDS: [&str; 4] = ["year", "volume", "number", "rating"];
+
+#[derive(Debug, PartialEq, PartialOrd)]
+enum SortKey {
+    Number(i64),
+    Text(String),
+}
+
+fn leading_number(value: &str) -> Option<i64> {
+    let digits = value.trim().chars().take_while(|c| c.is_ascii_digit()).collect::<String>();
+    digits.parse().ok()
+}
+
+fn sort_key(key: &str, paper: &json::JsonValue, field: &str) -> Option<SortKey> {
+    if field == "key" {
+        return Some(SortKey::Text(key.to_lowercase()))
+    }
+    let value = reading::field(paper, field);
+    if let Some(number) = value.as_i64() {
+        return Some(SortKey::Number(number))
+    }
+    let value = match value.as_ref() {
+        json::JsonValue::Array(values) => values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(" and "),
+        json::JsonValue::Null => return None,
+        value => value.to_string(),
+    };
+    if NUMERIC_FIELDS.contains(&field) {
+        if let Some(number) = leading_number(&value) {
+            return Some(SortKey::Number(number))
+        }
+    }
+    let text = if bibtex::NAME_FIELDS.contains(&field) {
+        bibtex::split_authors(&value).iter()
+            .map(|name| bibtex::last_name(name))
+            .collect::<Vec<_>>()
+            .join(" ")
+    } else {
+        value
+    };
+    return Some(SortKey::Text(string_cleaner::clean_and_decode(&text).to_lowercase()))
+}
+
+/// Sort the selection by a field. Papers without the field always come last.
+pub fn sort_selection(selection: json::JsonValue, field: &str, descending: bool) -> json::JsonValue {
+    let mut papers = selection.entries()
+        .map(|(key, paper)| (sort_key(key, paper, field), key.to_string(), paper.clone()))
+        .collect::<Vec<_>>();
+    papers.sort_by(|(a, _, _), (b, _, _)| match (a, b) {
+        (Some(a), Some(b)) => {
+            let ordering = a.partial_cmp(b).unwrap_or(Ordering::Equal);
+            if descending { ordering.reverse() } else { ordering }
+        },
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => Ordering::Equal,
+    });
+    let mut sorted = json::object!{};
+    for (_, key, paper) in papers {
+        sorted[key] = paper;
+    }
+    return sorted
+}
+
+/// Keep `count` papers from the start, or from the end if `from_end` is set.
+pub fn limit_selection(selection: json::JsonValue, count: usize, from_end: bool) -> json::JsonValue {
+    let skip = if from_end { selection.len().saturating_sub(count) } else { 0 };
+    let mut limited = json::object!{};
+    for (key, paper) in selection.entries().skip(skip).take(count) {
+        limited[key] = paper.clone();
+    }
+    return limited
+}
+
+#[cfg(test)]
+fn sample_selection() -> json::JsonValue {
+    let mut selection = json::object!{};
+    selection["b"] = json::object!{"year": "2017", "author": "Min Lyu and Dong Su", "title": "{U}nderstanding"};
+    selection["a"] = json::object!{"year": "9999", "author": "Zed, A.", "title": "\\'Etude"};
+    selection["c"] = json::object!{"year": "2020", "author": "Sara Abbott"};
+    selection["d"] = json::object!{"author": "Bob Lyu"};
+    selection
+}
+
+#[cfg(test)]
+fn keys(selection: &json::JsonValue) -> Vec<&str> {
+    selection.entries().map(|(key, _)| key).collect()
+}
+
+#[test]
+fn sort_numeric_year() {
+    let sorted = sort_selection(sample_selection(), "year", false);
+    assert_eq!(keys(&sorted), vec!["b", "c", "a", "d"]);
+    let sorted = sort_selection(sample_selection(), "year", true);
+    assert_eq!(keys(&sorted), vec!["a", "c", "b", "d"]);
+}
+
+#[test]
+fn sort_authors_by_last_name() {
+    let sorted = sort_selection(sample_selection(), "author", false);
+    assert_eq!(keys(&sorted), vec!["c", "d", "b", "a"]);
+}
+
+#[test]
+fn sort_cleaned_title() {
+    let sorted = sort_selection(sample_selection(), "title", false);
+    assert_eq!(keys(&sorted), vec!["a", "b", "c", "d"]);
+}
+
+#[test]
+fn sort_by_key() {
+    let sorted = sort_selection(sample_selection(), "key", true);
+    assert_eq!(keys(&sorted), vec!["d", "c", "b", "a"]);
+}
+
+#[test]
+fn limit_head_and_tail() {
+    assert_eq!(keys(&limit_selection(sample_selection(), 2, false)), vec!["b", "a"]);
+    assert_eq!(keys(&limit_selection(sample_selection(), 2, true)), vec!["c", "d"]);
+    assert_eq!(keys(&limit_selection(sample_selection(), 10, true)).len(), 4);
+}
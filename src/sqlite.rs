@@ -0,0 +1,208 @@
+use crate::storage::Storage;
+use crate::string_cleaner;
+use rusqlite::{params, params_from_iter, Connection};
+use std::path::{Path, PathBuf};
+
+// Papers are stored as JSON in the papers table, with the author, year and
+// tags copied into indexed columns for filtering. The author column holds
+// the author field cleaned and lowercased, the way `by author` matches it,
+// with the names of a list joined. A tags field written as a single string
+// is one tag.
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS papers (key TEXT PRIMARY KEY, author TEXT, year TEXT, data TEXT NOT NULL);
+CREATE INDEX IF NOT EXISTS papers_author ON papers (author);
+CREATE INDEX IF NOT EXISTS papers_year ON papers (year);
+CREATE TABLE IF NOT EXISTS tags (key TEXT NOT NULL, tag TEXT NOT NULL, PRIMARY KEY (tag, key));
+CREATE INDEX IF NOT EXISTS tags_key ON tags (key);
+";
+
+pub struct Sqlite {
+    path: PathBuf,
+    connection: Connection,
+}
+
+impl Sqlite {
+    pub fn open(path: PathBuf) -> Sqlite {
+        let connection = Connection::open(&path).expect("Could not open SQLite database");
+        connection.execute_batch(SCHEMA).expect("Could not create SQLite tables");
+        Sqlite { path, connection }
+    }
+
+    fn query(&self, condition: &str, parameters: &[&str]) -> json::JsonValue {
+        let sql = format!("SELECT key, data FROM papers WHERE {} ORDER BY rowid", condition);
+        let mut statement = self.connection.prepare(&sql).expect("Failed to prepare SQLite query");
+        let rows = statement
+            .query_map(params_from_iter(parameters), |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)))
+            .expect("Failed to query SQLite database");
+        let mut papers = json::object!{};
+        for row in rows {
+            let (key, data) = row.expect("Failed to read SQLite row");
+            papers[key.as_str()] = json::parse(&data).expect("Failed to parse paper in SQLite database");
+        }
+        return papers
+    }
+}
+
+fn author_column(paper: &json::JsonValue) -> Option<String> {
+    let authors = match &paper["author"] {
+        json::JsonValue::Null => return None,
+        json::JsonValue::Array(authors) => authors.iter().map(|author| author.to_string()).collect::<Vec<_>>().join(" and "),
+        author => author.to_string(),
+    };
+    return Some(string_cleaner::clean_and_decode(&authors).to_lowercase())
+}
+
+/// The tags of a paper, which can also be a single string.
+fn tags(paper: &json::JsonValue) -> Vec<String> {
+    match &paper["tags"] {
+        json::JsonValue::Array(tags) => tags.iter().filter(|tag| !tag.is_null()).map(|tag| tag.to_string()).collect(),
+        json::JsonValue::Null => Vec::new(),
+        tag => vec![tag.to_string()],
+    }
+}
+
+fn year_column(paper: &json::JsonValue) -> Option<String> {
+    if paper["year"].is_null() { None } else { Some(paper["year"].to_string()) }
+}
+
+fn write_paper(connection: &Connection, key: &str, paper: &json::JsonValue) {
+    connection.execute(
+        "INSERT INTO papers (key, author, year, data) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT (key) DO UPDATE SET author = excluded.author, year = excluded.year, data = excluded.data",
+        params![key, author_column(paper), year_column(paper), paper.dump()],
+    ).expect("Failed to save paper in SQLite database");
+    connection.execute("DELETE FROM tags WHERE key = ?1", [key]).expect("Failed to save tags");
+    for tag in tags(paper) {
+        connection.execute("INSERT OR IGNORE INTO tags (key, tag) VALUES (?1, ?2)", [key, tag.as_str()])
+            .expect("Failed to save tags");
+    }
+}
+
+/// SQL condition and parameter matching `by field value`, if the field can
+/// be filtered in SQL.
+fn condition(field: &str, value: &str) -> Option<(String, String)> {
+    match field {
+        "author" => Some((String::from("instr(author, ?1) > 0"), string_cleaner::clean_and_decode(value).to_lowercase())),
+        "year" => Some((String::from("instr(year, ?1) > 0"), value.to_string())),
+        "tags" => Some((
+            String::from("key IN (SELECT key FROM tags WHERE instr(tag, ?1) > 0)"),
+            value.to_string(),
+        )),
+        "rating" => Some((String::from("CAST(json_extract(data, '$.rating') AS TEXT) = ?1"), value.to_string())),
+        // Titles are matched cleaned, statuses default to unread and venues
+        // also match their other names.
+        "title" | "status" | "venue" | "journal" | "booktitle" => None,
+        // Other fields are matched in their JSON text, so values that JSON
+        // escapes cannot be searched for.
+        field if field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') && !value.contains(['"', '\\']) => Some((
+            format!("instr(json_extract(data, '$.{}'), ?1) > 0", field),
+            value.to_string(),
+        )),
+        _ => None,
+    }
+}
+
+impl Storage for Sqlite {
+    fn load(&self) -> json::JsonValue {
+        self.query("1", &[])
+    }
+
+    fn save(&self, db: &json::JsonValue) {
+        let transaction = self.connection.unchecked_transaction().expect("Failed to start SQLite transaction");
+        transaction.execute_batch("DELETE FROM papers; DELETE FROM tags;").expect("Failed to clear SQLite database");
+        for (key, paper) in db.entries() {
+            write_paper(&transaction, key, paper);
+        }
+        transaction.commit().expect("Failed to commit SQLite transaction");
+    }
+
+    /// Write the rows of changed papers and delete those of removed ones.
+    fn update(&self, db: &json::JsonValue, keys: &[String]) {
+        let transaction = self.connection.unchecked_transaction().expect("Failed to start SQLite transaction");
+        for key in keys {
+            if db.has_key(key) {
+                write_paper(&transaction, key, &db[key.as_str()]);
+            } else {
+                transaction.execute("DELETE FROM papers WHERE key = ?1", [key]).expect("Failed to remove paper");
+                transaction.execute("DELETE FROM tags WHERE key = ?1", [key]).expect("Failed to remove tags");
+            }
+        }
+        transaction.commit().expect("Failed to commit SQLite transaction");
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn select(&self, field: &str, value: &str) -> Option<json::JsonValue> {
+        let (condition, parameter) = condition(field, value)?;
+        return Some(self.query(&condition, &[&parameter]))
+    }
+}
+
+#[cfg(test)]
+fn sample_storage() -> Sqlite {
+    let storage = Sqlite::open(PathBuf::from(":memory:"));
+    storage.save(&json::object!{
+        "LyuSL17": {"author": "Min Lyu and Dong Su", "year": "2017", "journal": "Proc. VLDB", "volume": "10", "tags": ["dp", "svt"]},
+        "Raisa21": {"author": "Ossi R{\\\"a}is{\\\"a}", "year": "2021", "tags": ["dp"]},
+    });
+    storage
+}
+
+#[test]
+fn save_and_load_in_order() {
+    let storage = sample_storage();
+    let mut db = storage.load();
+    assert_eq!(db.entries().map(|(key, _)| key).collect::<Vec<_>>(), vec!["LyuSL17", "Raisa21"]);
+    db["LyuSL17"]["year"] = json::from("2018");
+    db.remove("Raisa21");
+    storage.save(&db);
+    assert_eq!(storage.load(), db);
+    assert_eq!(storage.select("tags", "dp").unwrap().len(), 1);
+}
+
+#[test]
+fn update_changed_papers() {
+    let storage = sample_storage();
+    let mut db = storage.load();
+    db["LyuSL17"]["year"] = json::from(2018);
+    db["LyuSL17"]["tags"] = json::array!["svt"];
+    db.remove("Raisa21");
+    db["new"] = json::object!{"year": "2020"};
+    storage.update(&db, &[String::from("LyuSL17"), String::from("Raisa21"), String::from("new")]);
+    assert_eq!(storage.load(), db);
+    assert_eq!(storage.select("year", "2018").unwrap().len(), 1);
+    assert!(storage.select("tags", "dp").unwrap().is_empty());
+}
+
+#[test]
+fn select_authors_and_tags_of_any_shape() {
+    let storage = Sqlite::open(PathBuf::from(":memory:"));
+    storage.save(&json::object!{
+        "listed": {"author": ["Min Lyu", "Dong Su"], "tags": "dp"},
+        "numbered": {"author": 42, "tags": ["svt", 3]},
+    });
+    let keys = |field, value| storage.select(field, value).unwrap().entries()
+        .map(|(key, _)| key.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(keys("author", "Su"), vec!["listed"]);
+    assert_eq!(keys("tags", "dp"), vec!["listed"]);
+    assert_eq!(keys("author", "42"), vec!["numbered"]);
+    assert_eq!(keys("tags", "3"), vec!["numbered"]);
+}
+
+#[test]
+fn select_in_sql() {
+    let storage = sample_storage();
+    let keys = |field, value| storage.select(field, value).unwrap().entries()
+        .map(|(key, _)| key.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(keys("author", "räisä"), vec!["Raisa21"]);
+    assert_eq!(keys("year", "2017"), vec!["LyuSL17"]);
+    assert_eq!(keys("tags", "svt"), vec!["LyuSL17"]);
+    assert_eq!(keys("volume", "10"), vec!["LyuSL17"]);
+    assert!(storage.select("journal", "VLDB").is_none());
+    assert!(storage.select("title", "Sparse").is_none());
+}
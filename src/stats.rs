@@ -0,0 +1,195 @@
+use crate::bibtex;
+use crate::reading;
+use crate::string_cleaner;
+use crate::venues::{Venues, VENUE_FIELDS};
+use std::collections::HashMap;
+use std::path::Path;
+
+// Counts over a selection, computed as JSON and printed either as it is or
+// as a table with a histogram per field. Besides the fields of the papers,
+// `venue` is the journal or booktitle, by its full name when it is listed
+// in venues.json. Authors and editors are counted per person.
+
+/// Fields reported when none are asked for.
+pub const DEFAULT_FIELDS: [&str; 6] = ["year", "entry_type", "venue", "author", "tags", "status"];
+
+const BAR_WIDTH: usize = 40;
+
+/// The values of a field in a paper, decoded from LaTeX.
+fn field_values(paper: &json::JsonValue, field: &str, venues: &Venues) -> Vec<String> {
+    if field == "venue" {
+        return VENUE_FIELDS.iter()
+            .filter_map(|field| paper[*field].as_str())
+            .take(1)
+            .map(|name| match venues.find(name) {
+                Some(venue) => venue.full.clone(),
+                None => string_cleaner::clean_string(name),
+            })
+            .collect()
+    }
+    let value = reading::field(paper, field);
+    let values = match value.as_ref() {
+        json::JsonValue::Null => Vec::new(),
+        json::JsonValue::Array(items) => items.iter().map(|item| item.to_string()).collect(),
+        // Names are written first name first, so "Su, Dong" is "Dong Su".
+        value if bibtex::NAME_FIELDS.contains(&field) => bibtex::split_authors(&value.to_string()).iter()
+            .map(|name| bibtex::split_name(name))
+            .map(|(first, last)| format!("{} {}", first, last).trim().to_string())
+            .collect(),
+        value => vec![value.to_string()],
+    };
+    return values.iter()
+        .map(|value| string_cleaner::clean_string(value))
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+fn is_number(value: &str) -> bool {
+    !value.is_empty() && value.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Counts of the values of a field. Numeric fields such as the year keep
+/// all values in order, for a histogram. Others keep the `top` most common.
+fn field_stats(selection: &json::JsonValue, field: &str, top: usize, venues: &Venues) -> json::JsonValue {
+    let mut counted: HashMap<String, usize> = HashMap::new();
+    let mut missing = 0;
+    for (_, paper) in selection.entries() {
+        let values = field_values(paper, field, venues);
+        if values.is_empty() {
+            missing += 1;
+        }
+        for value in values {
+            *counted.entry(value).or_insert(0) += 1;
+        }
+    }
+    let distinct = counted.len();
+    let mut counts = counted.into_iter().collect::<Vec<_>>();
+    if counts.iter().all(|(value, _)| is_number(value)) {
+        counts.sort_by_key(|(value, _)| (value.parse::<u64>().unwrap_or(0), value.clone()));
+    } else {
+        counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then_with(|| a.cmp(b)));
+        counts.truncate(top);
+    }
+    let values = counts.into_iter()
+        .map(|(value, count)| json::object!{"value": value, "count": count})
+        .collect::<Vec<_>>();
+    return json::object!{"distinct": distinct, "missing": missing, "values": values}
+}
+
+/// Totals and the counts of each field. Papers count as having a PDF when
+/// their file exists.
+pub fn stats(selection: &json::JsonValue, fields: &[&str], top: usize, venues: &Venues) -> json::JsonValue {
+    let with_pdf = selection.entries()
+        .filter(|(_, paper)| paper["file"].as_str().is_some_and(|file| Path::new(file).exists()))
+        .count();
+    let with_doi = selection.entries().filter(|(_, paper)| !paper["doi"].is_null()).count();
+    let mut result = json::object!{
+        "papers": selection.len(),
+        "with_pdf": with_pdf,
+        "without_pdf": selection.len() - with_pdf,
+        "with_doi": with_doi,
+        "fields": {},
+    };
+    for field in fields {
+        result["fields"][*field] = field_stats(selection, field, top, venues);
+    }
+    return result
+}
+
+/// Stats as text, with a bar for each count. The totals are only shown
+/// with `totals` set.
+pub fn format_table(stats: &json::JsonValue, totals: bool) -> String {
+    let mut result = String::new();
+    if totals {
+        for (label, name) in [("Papers", "papers"), ("With PDF", "with_pdf"), ("Without PDF", "without_pdf"), ("With DOI", "with_doi")].iter() {
+            result.push_str(&format!("{:<12} {:>6}\n", label, stats[*name].as_usize().unwrap_or(0)));
+        }
+    }
+    for (field, counts) in stats["fields"].entries() {
+        if !result.is_empty() {
+            result.push('\n');
+        }
+        let shown = counts["values"].len();
+        let distinct = counts["distinct"].as_usize().unwrap_or(0);
+        if shown < distinct {
+            result.push_str(&format!("{} (top {} of {})\n", field, shown, distinct));
+        } else {
+            result.push_str(&format!("{}\n", field));
+        }
+        let mut rows = counts["values"].members()
+            .map(|row| (row["value"].as_str().unwrap_or("").to_string(), row["count"].as_usize().unwrap_or(0)))
+            .collect::<Vec<_>>();
+        let missing = counts["missing"].as_usize().unwrap_or(0);
+        if missing > 0 {
+            rows.push((String::from("(missing)"), missing));
+        }
+        let width = rows.iter().map(|(value, _)| value.chars().count()).max().unwrap_or(0);
+        let largest = rows.iter().map(|(_, count)| *count).max().unwrap_or(0).max(1);
+        for (value, count) in rows {
+            let bar = "#".repeat((count * BAR_WIDTH).div_ceil(largest));
+            result.push_str(&format!("  {:<width$} {:>6} {}\n", value, count, bar, width = width));
+        }
+    }
+    return result
+}
+
+#[cfg(test)]
+fn sample_selection() -> json::JsonValue {
+    json::object!{
+        "a": {"author": "Min Lyu and Dong Su", "year": "2017", "journal": "Proc. {VLDB} Endow.", "file": concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"), "tags": ["dp"]},
+        "b": {"author": "Su, Dong", "year": "2016", "journal": "PVLDB", "status": "read", "doi": "10.1/b", "file": "/nonexistent/b.pdf"},
+        "c": {"author": "R{\\\"a}is{\\\"a}, Ossi", "year": "2017", "booktitle": "ICML", "tags": ["dp", "ml"]},
+    }
+}
+
+#[cfg(test)]
+fn sample_venues() -> Venues {
+    Venues::from_json(&json::array![
+        {"full": "Proceedings of the VLDB Endowment", "abbrev": "Proc. VLDB Endow.", "aliases": ["PVLDB"]},
+    ])
+}
+
+#[cfg(test)]
+fn counts(stats: &json::JsonValue, field: &str) -> Vec<(String, usize)> {
+    stats["fields"][field]["values"].members()
+        .map(|row| (row["value"].to_string(), row["count"].as_usize().unwrap()))
+        .collect()
+}
+
+#[cfg(test)]
+fn pairs(pairs: &[(&str, usize)]) -> Vec<(String, usize)> {
+    pairs.iter().map(|(value, count)| (value.to_string(), *count)).collect()
+}
+
+#[test]
+fn count_fields() {
+    let stats = stats(&sample_selection(), &DEFAULT_FIELDS, 10, &sample_venues());
+    assert_eq!((stats["papers"].as_usize(), stats["without_pdf"].as_usize(), stats["with_doi"].as_usize()), (Some(3), Some(2), Some(1)));
+    assert_eq!(counts(&stats, "year"), pairs(&[("2016", 1), ("2017", 2)]));
+    assert_eq!(counts(&stats, "venue"), pairs(&[("Proceedings of the VLDB Endowment", 2), ("ICML", 1)]));
+    assert_eq!(counts(&stats, "author"), pairs(&[("Dong Su", 2), ("Min Lyu", 1), ("Ossi Räisä", 1)]));
+    assert_eq!(counts(&stats, "tags"), pairs(&[("dp", 2), ("ml", 1)]));
+    assert_eq!(stats["fields"]["tags"]["missing"], 1);
+    assert_eq!(counts(&stats, "status"), pairs(&[("unread", 2), ("read", 1)]));
+}
+
+#[test]
+fn keep_top_values() {
+    let stats = stats(&sample_selection(), &["author"], 1, &sample_venues());
+    assert_eq!(counts(&stats, "author"), pairs(&[("Dong Su", 2)]));
+    assert_eq!(stats["fields"]["author"]["distinct"], 3);
+}
+
+#[test]
+fn table_with_histogram() {
+    let stats = stats(&sample_selection(), &["year", "tags"], 1, &sample_venues());
+    assert_eq!(
+        format_table(&stats, true),
+        format!(
+            "Papers            3\nWith PDF          1\nWithout PDF       2\nWith DOI          1\n\n\
+             year\n  2016      1 {}\n  2017      2 {}\n\n\
+             tags (top 1 of 2)\n  dp             2 {}\n  (missing)      1 {}\n",
+            "#".repeat(20), "#".repeat(40), "#".repeat(40), "#".repeat(20),
+        )
+    );
+}
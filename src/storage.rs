@@ -0,0 +1,383 @@
+use crate::bibtex;
+use crate::string_cleaner;
+use crate::versioning;
+use std::cell::{OnceCell, Ref, RefCell};
+use std::collections::HashSet;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+
+// Where the library is kept, chosen with "storage" in the config:
+//
+//   "json"       one JSON file with all papers (the default)
+//   "directory"  one file per paper in the entries directory, written as
+//                BibTeX or JSON depending on "entry_format" ("bib" or "json")
+//   "sqlite"     an SQLite database, see sqlite.rs, which cannot be
+//                versioned with git
+
+/// Fields stored as lists, which BibTeX files keep comma separated.
+const LIST_FIELDS: [&str; 1] = ["tags"];
+
+/// Comment naming the other list fields of a paper in its BibTeX file.
+const LISTS_COMMENT: &str = "% lists: ";
+
+pub trait Storage {
+    fn load(&self) -> json::JsonValue;
+    fn save(&self, db: &json::JsonValue);
+    /// Write the papers with the given keys as they are in `db`, removing
+    /// those it no longer has. Storages that keep papers apart write only
+    /// those papers.
+    fn update(&self, db: &json::JsonValue, _keys: &[String]) {
+        self.save(db)
+    }
+    /// The file or directory holding the library.
+    fn path(&self) -> &Path;
+    /// The papers that may match `by field value`, for storages that can
+    /// find them without loading the whole library. Papers that do not
+    /// match can be included, as the selection is filtered again.
+    fn select(&self, _field: &str, _value: &str) -> Option<json::JsonValue> {
+        None
+    }
+}
+
+/// The papers of a storage, loaded when first used.
+pub struct Library {
+    storage: Box<dyn Storage>,
+    papers: OnceCell<json::JsonValue>,
+    /// The papers as last loaded or saved, to find those that changed.
+    saved: RefCell<json::JsonValue>,
+}
+
+impl Library {
+    pub fn new(storage: Box<dyn Storage>) -> Library {
+        Library { storage, papers: OnceCell::new(), saved: RefCell::new(json::object!{}) }
+    }
+
+    pub fn storage(&self) -> &dyn Storage {
+        self.storage.as_ref()
+    }
+
+    fn papers(&self) -> &json::JsonValue {
+        self.papers.get_or_init(|| {
+            let papers = self.storage.load();
+            *self.saved.borrow_mut() = papers.clone();
+            papers
+        })
+    }
+
+    /// The papers as last loaded or saved.
+    pub fn saved(&self) -> Ref<'_, json::JsonValue> {
+        self.papers();
+        self.saved.borrow()
+    }
+
+    /// Write the papers that changed since they were loaded or last saved.
+    pub fn save(&self) {
+        let papers = match self.papers.get() {
+            Some(papers) => papers,
+            None => return,
+        };
+        let changed = changed_keys(&self.saved.borrow(), papers);
+        if !changed.is_empty() {
+            self.storage.update(papers, &changed);
+            *self.saved.borrow_mut() = papers.clone();
+        }
+    }
+}
+
+/// Keys of the papers added, changed or removed between two versions.
+fn changed_keys(before: &json::JsonValue, after: &json::JsonValue) -> Vec<String> {
+    let changed = after.entries()
+        .filter(|(key, paper)| before[*key] != **paper)
+        .map(|(key, _)| key.to_string());
+    let removed = before.entries()
+        .filter(|(key, _)| !after.has_key(key))
+        .map(|(key, _)| key.to_string());
+    return changed.chain(removed).collect()
+}
+
+impl Deref for Library {
+    type Target = json::JsonValue;
+
+    fn deref(&self) -> &json::JsonValue {
+        self.papers()
+    }
+}
+
+impl DerefMut for Library {
+    fn deref_mut(&mut self) -> &mut json::JsonValue {
+        self.papers();
+        self.papers.get_mut().expect("Library not loaded")
+    }
+}
+
+pub struct JsonFile {
+    path: PathBuf,
+    stable: bool,
+}
+
+pub struct Directory {
+    path: PathBuf,
+    format: EntryFormat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntryFormat {
+    Bib,
+    Json,
+}
+
+impl EntryFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            EntryFormat::Bib => "bib",
+            EntryFormat::Json => "json",
+        }
+    }
+}
+
+impl JsonFile {
+    /// A stable file is pretty-printed with papers and fields sorted.
+    pub fn new(path: PathBuf, stable: bool) -> JsonFile {
+        JsonFile { path, stable }
+    }
+}
+
+impl Directory {
+    pub fn new(path: PathBuf, format: EntryFormat) -> Directory {
+        Directory { path, format }
+    }
+
+    fn entry_contents(&self, key: &str, paper: &json::JsonValue) -> String {
+        let mut entry = json::object!{};
+        entry[key] = paper.clone();
+        match self.format {
+            EntryFormat::Bib => {
+                let bibtex = bibtex::generate_stored_bibtex(entry, bibtex::Dialect::Bibtex);
+                let lists = paper.entries()
+                    .filter(|(field, value)| value.is_array() && !LIST_FIELDS.contains(field))
+                    .map(|(field, _)| field)
+                    .collect::<Vec<_>>();
+                if lists.is_empty() { bibtex } else { format!("{}{}\n{}", LISTS_COMMENT, lists.join(", "), bibtex) }
+            },
+            EntryFormat::Json => versioning::stable_dump(&entry),
+        }
+    }
+
+    fn parse_entries(&self, contents: &str) -> Result<json::JsonValue, String> {
+        match self.format {
+            EntryFormat::Bib => {
+                let mut entries = bibtex::try_parse_bibtex(contents)?;
+                let lists = contents.lines()
+                    .filter_map(|line| line.strip_prefix(LISTS_COMMENT))
+                    .flat_map(|fields| fields.split(',').map(str::trim))
+                    .chain(LIST_FIELDS.iter().copied())
+                    .collect::<Vec<_>>();
+                for (_, paper) in entries.entries_mut() {
+                    split_list_fields(paper, &lists);
+                }
+                Ok(entries)
+            },
+            EntryFormat::Json => json::parse(contents).map_err(|error| error.to_string()),
+        }
+    }
+
+    fn entry_files(&self) -> Vec<PathBuf> {
+        let entries = match std::fs::read_dir(&self.path) {
+            Ok(entries) => entries,
+            Err(error) => match error.kind() {
+                std::io::ErrorKind::NotFound => return Vec::new(),
+                _ => panic!("Could not open entries directory: {:?}", error),
+            }
+        };
+        let mut files = entries
+            .map(|entry| entry.expect("Could not read entries directory").path())
+            .filter(|path| path.extension().and_then(|extension| extension.to_str()) == Some(self.format.extension()))
+            .collect::<Vec<_>>();
+        files.sort();
+        return files
+    }
+}
+
+fn split_list_fields(paper: &mut json::JsonValue, lists: &[&str]) {
+    for field in lists {
+        if paper[*field].is_string() {
+            let items = paper[*field].to_string().split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(String::from)
+                .collect::<Vec<_>>();
+            paper[*field] = json::from(items);
+        }
+    }
+}
+
+impl Storage for JsonFile {
+    fn load(&self) -> json::JsonValue {
+        match std::fs::read_to_string(&self.path) {
+            Ok(json) => json::parse(&json).unwrap(),
+            Err(error) => match error.kind() {
+                std::io::ErrorKind::NotFound => json::object!{},
+                _ => panic!("Could not open database file: {:?}", error),
+            }
+        }
+    }
+
+    fn save(&self, db: &json::JsonValue) {
+        let contents = if self.stable { versioning::stable_dump(db) } else { db.dump() };
+        if let Err(error) = std::fs::write(&self.path, contents) {
+            panic!("Could not save database file: {:?}", error)
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Storage for Directory {
+    fn load(&self) -> json::JsonValue {
+        let mut db = json::object!{};
+        for file in self.entry_files() {
+            let contents = std::fs::read_to_string(&file).expect("Could not read entry file");
+            match self.parse_entries(&contents) {
+                Ok(entries) => for (key, paper) in entries.entries() {
+                    db[key] = paper.clone();
+                },
+                Err(error) => eprintln!("Skipping {:?}, which could not be parsed: {}", file, error),
+            }
+        }
+        return db
+    }
+
+    fn save(&self, db: &json::JsonValue) {
+        let keys = db.entries().map(|(key, _)| key.to_string()).collect::<Vec<_>>();
+        self.update(db, &keys);
+    }
+
+    /// Write the files of changed papers and delete those of removed ones.
+    fn update(&self, db: &json::JsonValue, keys: &[String]) {
+        std::fs::create_dir_all(&self.path).expect("Could not create entries directory");
+        let keys = keys.iter().map(String::as_str).collect::<HashSet<_>>();
+        // Papers whose keys share a stem with a changed one can move to
+        // another file.
+        let moved = keys.iter().map(|key| string_cleaner::key_file_stem(key)).collect::<HashSet<_>>();
+        let stems = string_cleaner::unique_file_stems(db.entries().map(|(key, _)| key));
+        let mut written = HashSet::new();
+        for (key, paper) in db.entries() {
+            let file = self.path.join(format!("{}.{}", stems[key], self.format.extension()));
+            if keys.contains(key) || moved.contains(&string_cleaner::key_file_stem(key)) {
+                let contents = self.entry_contents(key, paper);
+                if std::fs::read_to_string(&file).ok().as_deref() != Some(contents.as_str()) {
+                    std::fs::write(&file, contents).expect("Could not save entry file");
+                }
+            }
+            written.insert(file);
+        }
+        // Files that could not be parsed were skipped when loading, and are
+        // kept for fixing by hand.
+        for file in self.entry_files() {
+            let parses = || std::fs::read_to_string(&file).ok().is_some_and(|contents| self.parse_entries(&contents).is_ok());
+            if !written.contains(&file) && parses() {
+                std::fs::remove_file(&file).expect("Could not remove entry file");
+            }
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+#[cfg(test)]
+fn round_trip(format: EntryFormat) {
+    let dir = std::env::temp_dir().join(format!("paperman-storage-{}-{}", format.extension(), std::process::id()));
+    let storage = Directory::new(dir.clone(), format);
+    let mut db = json::object!{
+        "DBLP:journals/pvldb/LyuSL17": {"entry_type": "article", "title": "Sparse", "tags": ["dp", "svt"], "keywords": ["privacy"]},
+        "other": {"entry_type": "misc", "title": "Other"},
+    };
+    storage.save(&db);
+    assert!(dir.join(format!("DBLP_journals_pvldb_LyuSL17.{}", format.extension())).exists());
+    assert_eq!(storage.load()["DBLP:journals/pvldb/LyuSL17"]["tags"], json::array!["dp", "svt"]);
+    assert_eq!(storage.load()["DBLP:journals/pvldb/LyuSL17"]["keywords"], json::array!["privacy"]);
+
+    db.remove("other");
+    storage.save(&db);
+    let loaded = storage.load();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(loaded.len(), 1);
+    assert_eq!(loaded["DBLP:journals/pvldb/LyuSL17"]["title"], "Sparse");
+}
+
+#[test]
+fn directory_of_bib_files() {
+    round_trip(EntryFormat::Bib);
+}
+
+#[test]
+fn directory_of_json_files() {
+    round_trip(EntryFormat::Json);
+}
+
+#[test]
+fn file_stems_independent_of_order() {
+    let stems = string_cleaner::unique_file_stems(["a_b_2", "a:b", "a/b", "c"].iter().copied());
+    assert_eq!(stems, string_cleaner::unique_file_stems(["c", "a/b", "a:b", "a_b_2"].iter().copied()));
+    assert_eq!((stems["a/b"].as_str(), stems["a:b"].as_str(), stems["a_b_2"].as_str()), ("a_b", "a_b_3", "a_b_2"));
+}
+
+#[test]
+fn keep_unparsable_entry_files() {
+    let dir = std::env::temp_dir().join(format!("paperman-storage-broken-{}", std::process::id()));
+    let storage = Directory::new(dir.clone(), EntryFormat::Bib);
+    storage.save(&json::object!{"a:b": {"entry_type": "misc", "title": "A"}, "a/b": {"entry_type": "misc", "title": "B"}});
+    std::fs::write(dir.join("broken.bib"), "@misc{broken, title = {Unclosed}").unwrap();
+    let mut db = storage.load();
+    assert_eq!(db.len(), 2);
+    db.remove("a/b");
+    storage.update(&db, &[String::from("a/b")]);
+    let loaded = storage.load();
+    let kept = dir.join("broken.bib").exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(kept);
+    assert_eq!(loaded, json::object!{"a:b": {"entry_type": "misc", "title": "A"}});
+}
+
+#[cfg(test)]
+struct Recorder {
+    updated: std::rc::Rc<RefCell<Vec<String>>>,
+}
+
+#[cfg(test)]
+impl Storage for Recorder {
+    fn load(&self) -> json::JsonValue {
+        json::object!{"a": {"title": "A"}, "b": {"title": "B"}, "c": {"title": "C"}}
+    }
+
+    fn save(&self, _db: &json::JsonValue) {
+        panic!("Saved the whole library")
+    }
+
+    fn update(&self, _db: &json::JsonValue, keys: &[String]) {
+        self.updated.borrow_mut().extend(keys.iter().cloned());
+    }
+
+    fn path(&self) -> &Path {
+        Path::new("")
+    }
+}
+
+#[test]
+fn library_saves_changed_papers() {
+    let updated = std::rc::Rc::new(RefCell::new(Vec::new()));
+    let mut library = Library::new(Box::new(Recorder { updated: updated.clone() }));
+    library.save();
+    library["a"]["title"] = json::from("Changed");
+    library.remove("b");
+    library["d"] = json::object!{"title": "D"};
+    library.save();
+    assert_eq!(*updated.borrow(), vec!["a", "d", "b"]);
+    library.save();
+    assert_eq!(updated.borrow().len(), 3);
+    assert_eq!(library.saved()["a"]["title"], "Changed");
+}
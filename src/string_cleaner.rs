@@ -1,4 +1,5 @@
 use crate::latex_decoder;
+use std::collections::{HashMap, HashSet};
 use unidecode::unidecode;
 
 pub fn clean_and_decode(s: &str) -> String {
@@ -6,13 +7,61 @@ pub fn clean_and_decode(s: &str) -> String {
     return unidecode(&cleaned)
 }
 
-pub fn clean_string<'a>(s: &'a str) -> String {
+pub fn clean_string(s: &str) -> String {
     let decoded = latex_decoder::decode_latex(s);
     return remove_extra_whitespace(&decoded)
 }
 
-fn remove_extra_whitespace(s: &str) -> String {
+pub fn remove_extra_whitespace(s: &str) -> String {
     let split = s.split(char::is_whitespace);
     return split.map(|s| s.trim()).filter(|s| !s.is_empty()).collect::<Vec<&str>>().join(" ");
 }
 
+
+/// Split plain text into lowercase ASCII words for indexing and searching.
+pub fn tokenize(s: &str) -> Vec<String> {
+    let ascii = unidecode(s).to_lowercase();
+    return ascii.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+
+/// File name stem for per-paper files, with anything but ASCII letters and
+/// digits in the citation key replaced by underscores.
+pub fn key_file_stem(key: &str) -> String {
+    key.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// File name stems for citation keys, with a suffix such as `_2` for keys
+/// whose stem is taken. Keys keep their plain stem where they can, and the
+/// suffixes go to the others in sorted order, so each key gets the same
+/// stem whatever order the keys come in.
+pub fn unique_file_stems<'a>(keys: impl Iterator<Item = &'a str>) -> HashMap<String, String> {
+    let mut keys = keys.collect::<Vec<_>>();
+    keys.sort_unstable();
+    let mut taken = HashSet::new();
+    let mut stems = HashMap::new();
+    let mut clashing = Vec::new();
+    for key in keys {
+        let stem = key_file_stem(key);
+        if taken.insert(stem.clone()) {
+            stems.insert(key.to_string(), stem);
+        } else {
+            clashing.push((key, stem));
+        }
+    }
+    for (key, stem) in clashing {
+        let mut suffix = 2;
+        while taken.contains(&format!("{}_{}", stem, suffix)) {
+            suffix += 1;
+        }
+        let unique = format!("{}_{}", stem, suffix);
+        taken.insert(unique.clone());
+        stems.insert(key.to_string(), unique);
+    }
+    return stems
+}
@@ -0,0 +1,271 @@
+use crate::bibtex;
+use crate::citation::Markup;
+use crate::latex_decoder;
+use crate::reading;
+use crate::string_cleaner;
+use std::str::FromStr;
+
+// A template is plain text with `{field|filter|filter:argument}` placeholders.
+// `{key}` is the citation key, `\t` and `\n` are tab and newline, and `{{`
+// and `}}` are literal braces. `first` and `lastnames` work on the names of
+// the author and editor fields; other fields are not split into names, so
+// `first` keeps the first item of a list such as tags.
+
+const PRESETS: [(&str, &str); 3] = [
+    ("short", "{key}: {title|clean}"),
+    ("long", "{key}: {author|lastnames|clean} ({year|default:n.d.}). {title|clean}"),
+    ("tsv", "{key}\\t{entry_type}\\t{year}\\t{author|lastnames|clean}\\t{title|clean}"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Filter {
+    First,
+    Lastnames,
+    Clean,
+    Upper,
+    Lower,
+    Render(Markup),
+    Default(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Text(String),
+    Field(String, Vec<Filter>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    segments: Vec<Segment>,
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Filter, String> {
+        let (name, argument) = match s.find(':') {
+            Some(index) => (&s[..index], Some(&s[index + 1..])),
+            None => (s, None),
+        };
+        match (name.trim(), argument) {
+            ("first", None) => Ok(Filter::First),
+            ("lastnames", None) => Ok(Filter::Lastnames),
+            ("clean", None) => Ok(Filter::Clean),
+            ("upper", None) => Ok(Filter::Upper),
+            ("lower", None) => Ok(Filter::Lower),
+            ("html", None) => Ok(Filter::Render(Markup::Html)),
+            ("markdown", None) => Ok(Filter::Render(Markup::Markdown)),
+            ("default", Some(argument)) => Ok(Filter::Default(argument.to_string())),
+            ("default", None) => Ok(Filter::Default(String::new())),
+            _ => Err(format!("Unknown template filter: {}", s)),
+        }
+    }
+}
+
+impl FromStr for Template {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Template, String> {
+        let template = PRESETS.iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, template)| *template)
+            .unwrap_or(s);
+        return Template::parse(template)
+    }
+}
+
+fn push_text(segments: &mut Vec<Segment>, text: &mut String) {
+    if !text.is_empty() {
+        segments.push(Segment::Text(std::mem::take(text)));
+    }
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Template, String> {
+        let mut segments = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => text.push('\t'),
+                    Some('n') => text.push('\n'),
+                    Some(other) => {
+                        text.push('\\');
+                        text.push(other);
+                    },
+                    None => text.push('\\'),
+                },
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                },
+                '{' => {
+                    let mut placeholder = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(c) => placeholder.push(c),
+                            None => return Err(format!("Unclosed '{{' in template: {}", template)),
+                        }
+                    }
+                    let mut parts = placeholder.split('|');
+                    let name = parts.next().unwrap_or("").trim().to_string();
+                    if name.is_empty() {
+                        return Err(format!("Empty placeholder in template: {}", template))
+                    }
+                    let filters = parts.map(str::parse).collect::<Result<Vec<Filter>, String>>()?;
+                    push_text(&mut segments, &mut text);
+                    segments.push(Segment::Field(name, filters));
+                },
+                '}' => return Err(format!("Unmatched '}}' in template: {}", template)),
+                c => text.push(c),
+            }
+        }
+        push_text(&mut segments, &mut text);
+        return Ok(Template { segments })
+    }
+
+    /// Template printing a single cleaned field.
+    pub fn field(field: &str) -> Template {
+        Template {
+            segments: vec![Segment::Field(field.to_string(), vec![Filter::Clean])],
+        }
+    }
+
+    pub fn render(&self, key: &str, paper: &json::JsonValue) -> String {
+        let mut result = String::new();
+        for segment in &self.segments {
+            match segment {
+                Segment::Text(text) => result.push_str(text),
+                Segment::Field(name, filters) => {
+                    let values = if name == "key" {
+                        vec![key.to_string()]
+                    } else {
+                        field_values(&reading::field(paper, name))
+                    };
+                    let values = filters.iter().fold(values, |values, filter| apply_filter(filter, name, values));
+                    result.push_str(&values.join(", "));
+                },
+            }
+        }
+        return result
+    }
+}
+
+fn field_values(value: &json::JsonValue) -> Vec<String> {
+    match value {
+        json::JsonValue::Null => Vec::new(),
+        json::JsonValue::Array(values) => values.iter().flat_map(field_values).collect(),
+        value => vec![value.to_string()],
+    }
+}
+
+/// The names in the values of a name field, or the values of any other.
+fn names(field: &str, values: Vec<String>) -> Vec<String> {
+    if !bibtex::NAME_FIELDS.contains(&field) {
+        return values
+    }
+    values.iter().flat_map(|value| bibtex::split_authors(value)).collect()
+}
+
+fn apply_filter(filter: &Filter, field: &str, values: Vec<String>) -> Vec<String> {
+    match filter {
+        Filter::First => names(field, values).into_iter().take(1).collect(),
+        Filter::Lastnames if bibtex::NAME_FIELDS.contains(&field) => {
+            names(field, values).iter().map(|name| bibtex::last_name(name)).collect()
+        },
+        Filter::Lastnames => values,
+        Filter::Clean => values.iter().map(|value| string_cleaner::clean_string(value)).collect(),
+        Filter::Upper => values.iter().map(|value| value.to_uppercase()).collect(),
+        Filter::Lower => values.iter().map(|value| value.to_lowercase()).collect(),
+        Filter::Render(markup) => values.iter()
+            .map(|value| string_cleaner::remove_extra_whitespace(&latex_decoder::render_latex(value, *markup)))
+            .collect(),
+        Filter::Default(default) => {
+            if values.iter().all(|value| value.is_empty()) {
+                vec![default.clone()]
+            } else {
+                values
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+fn sample_paper() -> json::JsonValue {
+    json::object!{
+        "entry_type": "article",
+        "author": "Min Lyu and\n   Dong Su and\n   Ninghui Li",
+        "title": "Understanding the {Sparse} Vector Technique",
+        "year": "2017",
+        "tags": ["privacy", "dp"],
+    }
+}
+
+#[test]
+fn render_fields_and_filters() {
+    let template: Template = r"{key}\t{year}\t{author|first}: {title|clean}".parse().unwrap();
+    assert_eq!(
+        template.render("LyuSL17", &sample_paper()),
+        "LyuSL17\t2017\tMin Lyu: Understanding the Sparse Vector Technique"
+    );
+}
+
+#[test]
+fn render_lastnames_and_upper() {
+    let template: Template = "{author|lastnames|upper}".parse().unwrap();
+    assert_eq!(template.render("k", &sample_paper()), "LYU, SU, LI");
+}
+
+#[test]
+fn render_name_filters_only_split_names() {
+    let paper = json::object!{"title": "Rock and Roll", "editor": "Doe, Jane and Roe, Rick"};
+    let template: Template = "{title|first}|{title|lastnames}|{editor|first}|{editor|lastnames}".parse().unwrap();
+    assert_eq!(template.render("k", &paper), "Rock and Roll|Rock and Roll|Doe, Jane|Doe, Roe");
+}
+
+#[test]
+fn render_missing_field_and_default() {
+    let template: Template = "{journal}|{journal|default:none}|{tags}|{tags|first}".parse().unwrap();
+    assert_eq!(template.render("k", &sample_paper()), "|none|privacy, dp|privacy");
+}
+
+#[test]
+fn render_markup_filters() {
+    let paper = json::object!{"note": "See \\emph{Lemma 2} for $\\alpha < 1$"};
+    let template: Template = "{note|html}".parse().unwrap();
+    assert_eq!(
+        template.render("k", &paper),
+        "See <em>Lemma 2</em> for <span class=\"math inline\">\\(\\alpha &lt; 1\\)</span>"
+    );
+    let template: Template = "{note|markdown}".parse().unwrap();
+    assert_eq!(template.render("k", &paper), "See *Lemma 2* for $\\alpha < 1$");
+}
+
+#[test]
+fn render_literal_braces() {
+    let template: Template = "{{{year}}}".parse().unwrap();
+    assert_eq!(template.render("k", &sample_paper()), "{2017}");
+}
+
+#[test]
+fn render_preset() {
+    let template: Template = "long".parse().unwrap();
+    assert_eq!(
+        template.render("k", &sample_paper()),
+        "k: Lyu, Su, Li (2017). Understanding the Sparse Vector Technique"
+    );
+}
+
+#[test]
+fn parse_errors() {
+    assert!("{title|bogus}".parse::<Template>().is_err());
+    assert!("{}".parse::<Template>().is_err());
+    assert!("title}".parse::<Template>().is_err());
+    assert!("{title".parse::<Template>().is_err());
+}
@@ -0,0 +1,69 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Bookkeeping fields maintained on every paper in the library. Timestamps
+// are UTC in the form 2026-01-31T12:00:00Z, so they sort as strings.
+pub const ADDED: &str = "added";
+pub const MODIFIED: &str = "modified";
+pub const LAST_OPENED: &str = "last_opened";
+
+/// Civil date (year, month, day) of a day count since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    return (year, month, day)
+}
+
+pub fn format_timestamp(seconds: i64) -> String {
+    let (year, month, day) = civil_from_days(seconds.div_euclid(86_400));
+    let time = seconds.rem_euclid(86_400);
+    return format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z", year, month, day, time / 3600, time / 60 % 60, time % 60)
+}
+
+pub fn now() -> String {
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).expect("System clock before 1970").as_secs();
+    return format_timestamp(seconds as i64)
+}
+
+/// Check a date argument of the form YYYY-MM-DD.
+pub fn parse_date(date: &str) -> Result<String, String> {
+    let parts = date.split('-').collect::<Vec<_>>();
+    let valid = parts.len() == 3
+        && parts.iter().zip([4, 2, 2].iter()).all(|(part, length)| part.len() == *length && part.chars().all(|c| c.is_ascii_digit()))
+        && (1..=12).contains(&parts[1].parse::<u32>().unwrap_or(0))
+        && (1..=31).contains(&parts[2].parse::<u32>().unwrap_or(0));
+    if valid {
+        Ok(date.to_string())
+    } else {
+        Err(format!("Invalid date {:?}, expected YYYY-MM-DD", date))
+    }
+}
+
+#[test]
+fn format_epoch() {
+    assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+}
+
+#[test]
+fn format_leap_day() {
+    assert_eq!(format_timestamp(951_782_400 + 3_723), "2000-02-29T01:02:03Z");
+}
+
+#[test]
+fn format_recent_date() {
+    assert_eq!(format_timestamp(1_767_225_599), "2025-12-31T23:59:59Z");
+}
+
+#[test]
+fn parse_dates() {
+    assert_eq!(parse_date("2026-01-01"), Ok(String::from("2026-01-01")));
+    assert!(parse_date("2026-1-01").is_err());
+    assert!(parse_date("2026-13-01").is_err());
+    assert!(parse_date("yesterday").is_err());
+}
@@ -0,0 +1,283 @@
+use crate::app::App;
+use crate::bibtex::Dialect;
+use crate::commands::ExportCmd;
+use crate::filter;
+use crate::string_cleaner;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashMap;
+
+const HELP: &str = "q quit  / search  o open  y copy BibTeX  t tag  T untag  e edit  d remove";
+
+enum Mode {
+    Normal,
+    Search,
+    Tag,
+    Untag,
+    ConfirmRemove,
+}
+
+struct Browser {
+    papers: json::JsonValue,
+    notes: HashMap<String, String>,
+    query: String,
+    visible: Vec<String>,
+    list_state: ListState,
+    mode: Mode,
+    input: String,
+    status: String,
+}
+
+impl Browser {
+    fn new(papers: json::JsonValue, notes: HashMap<String, String>) -> Browser {
+        let mut browser = Browser {
+            papers,
+            notes,
+            query: String::new(),
+            visible: Vec::new(),
+            list_state: ListState::default(),
+            mode: Mode::Normal,
+            input: String::new(),
+            status: String::from(HELP),
+        };
+        browser.refilter();
+        browser
+    }
+
+    fn refilter(&mut self) {
+        self.visible = if self.query.trim().is_empty() {
+            self.papers.entries().map(|(key, _)| key.to_string()).collect()
+        } else {
+            filter::rank_fuzzy(&self.papers, &self.notes, &self.query, self.papers.len()).into_iter()
+                .map(|(key, _)| key)
+                .collect()
+        };
+        let selected = match self.list_state.selected() {
+            _ if self.visible.is_empty() => None,
+            Some(index) => Some(usize::min(index, self.visible.len() - 1)),
+            None => Some(0),
+        };
+        self.list_state.select(selected);
+    }
+
+    fn current(&self) -> Option<String> {
+        self.list_state.selected().and_then(|index| self.visible.get(index)).cloned()
+    }
+
+    fn move_by(&mut self, offset: isize) {
+        if self.visible.is_empty() {
+            return
+        }
+        let index = self.list_state.selected().unwrap_or(0) as isize + offset;
+        let index = index.clamp(0, self.visible.len() as isize - 1);
+        self.list_state.select(Some(index as usize));
+    }
+
+    /// Copy the papers back from the database after they were changed there.
+    fn refresh(&mut self, app: &App) {
+        let keys = self.papers.entries().map(|(key, _)| key.to_string()).collect::<Vec<_>>();
+        for key in keys {
+            if app.db().has_key(&key) {
+                self.papers[key.as_str()] = app.db()[key.as_str()].clone();
+            }
+        }
+        self.refilter();
+    }
+}
+
+fn title(paper: &json::JsonValue) -> String {
+    string_cleaner::clean_string(paper["title"].as_str().unwrap_or(""))
+}
+
+fn field_value(value: &json::JsonValue) -> String {
+    match value {
+        json::JsonValue::Array(items) => items.iter()
+            .map(|item| string_cleaner::clean_string(&item.to_string()))
+            .collect::<Vec<_>>()
+            .join(", "),
+        value => string_cleaner::clean_string(&value.to_string()),
+    }
+}
+
+fn draw(frame: &mut Frame, browser: &mut Browser) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Length(3), Constraint::Min(1), Constraint::Length(1)])
+        .split(frame.area());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(rows[1]);
+
+    let search = Paragraph::new(browser.query.as_str())
+        .block(Block::default().borders(Borders::ALL).title("Search"));
+    frame.render_widget(search, rows[0]);
+
+    let items = browser.visible.iter()
+        .map(|key| ListItem::new(title(&browser.papers[key.as_str()])))
+        .collect::<Vec<_>>();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(format!("Papers ({})", browser.visible.len())))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(list, columns[0], &mut browser.list_state);
+
+    let mut details = Vec::new();
+    if let Some(key) = browser.current() {
+        details.push(Line::from(Span::styled(key.clone(), Style::default().add_modifier(Modifier::BOLD))));
+        for (field, value) in browser.papers[key.as_str()].entries() {
+            details.push(Line::from(vec![
+                Span::styled(format!("{}: ", field), Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(field_value(value)),
+            ]));
+        }
+    }
+    let detail = Paragraph::new(details)
+        .wrap(Wrap { trim: false })
+        .block(Block::default().borders(Borders::ALL).title("Details"));
+    frame.render_widget(detail, columns[1]);
+
+    let status = match browser.mode {
+        Mode::Tag => format!("Add tag: {}", browser.input),
+        Mode::Untag => format!("Remove tag: {}", browser.input),
+        Mode::ConfirmRemove => String::from("Remove paper from library? (y/n)"),
+        _ => browser.status.clone(),
+    };
+    frame.render_widget(Paragraph::new(status), rows[2]);
+}
+
+/// Run a command that writes to the terminal outside of the full-screen view.
+fn suspended<T, F: FnOnce() -> T>(terminal: &mut DefaultTerminal, action: F) -> T {
+    ratatui::restore();
+    let result = action();
+    *terminal = ratatui::init();
+    return result
+}
+
+fn handle_normal(key: KeyCode, browser: &mut Browser, app: &mut App, terminal: &mut DefaultTerminal) -> bool {
+    let current = browser.current();
+    match key {
+        KeyCode::Char('q') | KeyCode::Esc => return false,
+        KeyCode::Down | KeyCode::Char('j') => browser.move_by(1),
+        KeyCode::Up | KeyCode::Char('k') => browser.move_by(-1),
+        KeyCode::PageDown => browser.move_by(10),
+        KeyCode::PageUp => browser.move_by(-10),
+        KeyCode::Char('/') => browser.mode = Mode::Search,
+        _ => (),
+    }
+    let key_name = match current {
+        Some(current) => current,
+        None => return true,
+    };
+    app.select(&key_name, &browser.papers[key_name.as_str()]);
+    match key {
+        KeyCode::Enter | KeyCode::Char('o') => {
+            suspended(terminal, || app.open_selected());
+            app.save_db();
+            browser.status = format!("Opened {}", key_name);
+        },
+        KeyCode::Char('y') => {
+            let params = ExportCmd { format: String::from("bibtex"), dialect: Dialect::Bibtex, clipboard: true, venue: None, ascii: false };
+            app.export_selected(&params);
+            browser.status = format!("Copied BibTeX of {}", key_name);
+        },
+        KeyCode::Char('e') => {
+            if let Err(error) = suspended(terminal, || app.edit_selected()) {
+                browser.status = error;
+                return true
+            }
+            app.save_db();
+            browser.papers.remove(&key_name);
+            for (key, paper) in app.selection().entries() {
+                browser.papers[key] = paper.clone();
+            }
+            browser.refilter();
+            browser.status = format!("Edited {}", key_name);
+        },
+        KeyCode::Char('t') => {
+            browser.input.clear();
+            browser.mode = Mode::Tag;
+        },
+        KeyCode::Char('T') => {
+            browser.input.clear();
+            browser.mode = Mode::Untag;
+        },
+        KeyCode::Char('d') => browser.mode = Mode::ConfirmRemove,
+        _ => (),
+    }
+    return true
+}
+
+fn handle_input(key: KeyCode, browser: &mut Browser, app: &mut App) {
+    match key {
+        KeyCode::Esc => browser.mode = Mode::Normal,
+        KeyCode::Backspace => {
+            browser.input.pop();
+        },
+        KeyCode::Char(c) => browser.input.push(c),
+        KeyCode::Enter => {
+            let tag = browser.input.trim().to_string();
+            if let (false, Some(key)) = (tag.is_empty(), browser.current()) {
+                app.select(&key, &browser.papers[key.as_str()]);
+                match browser.mode {
+                    Mode::Tag => app.tag_selected(&tag),
+                    _ => app.untag_selected(&tag),
+                }
+                app.save_db();
+                browser.refresh(app);
+                browser.status = format!("Updated tags of {}", key);
+            }
+            browser.mode = Mode::Normal;
+        },
+        _ => (),
+    }
+}
+
+pub fn run(app: &mut App) {
+    let mut browser = Browser::new(app.selection().clone(), app.notes());
+    let mut terminal = ratatui::init();
+    loop {
+        terminal.draw(|frame| draw(frame, &mut browser)).expect("Failed to draw terminal");
+        let key = match event::read().expect("Failed to read terminal event") {
+            Event::Key(key) if key.kind == KeyEventKind::Press => key.code,
+            _ => continue,
+        };
+        match browser.mode {
+            Mode::Normal => {
+                if !handle_normal(key, &mut browser, app, &mut terminal) {
+                    break
+                }
+            },
+            Mode::Search => match key {
+                KeyCode::Enter | KeyCode::Esc => browser.mode = Mode::Normal,
+                KeyCode::Backspace => {
+                    browser.query.pop();
+                    browser.refilter();
+                },
+                KeyCode::Char(c) => {
+                    browser.query.push(c);
+                    browser.refilter();
+                },
+                _ => (),
+            },
+            Mode::Tag | Mode::Untag => handle_input(key, &mut browser, app),
+            Mode::ConfirmRemove => {
+                if key == KeyCode::Char('y') {
+                    if let Some(key) = browser.current() {
+                        app.select(&key, &browser.papers[key.as_str()]);
+                        app.remove_selected();
+                        app.save_db();
+                        browser.papers.remove(&key);
+                        browser.refilter();
+                        browser.status = format!("Removed {}", key);
+                    }
+                }
+                browser.mode = Mode::Normal;
+            },
+        }
+    }
+    ratatui::restore();
+}
@@ -0,0 +1,169 @@
+use crate::string_cleaner;
+use std::path::Path;
+use std::str::FromStr;
+
+// Journals and conferences are listed in venues.json beside the database,
+// with their full name, ISO4 abbreviation and any other names they go by:
+//
+//   [
+//     {
+//       "full": "Proceedings of the VLDB Endowment",
+//       "abbrev": "Proc. VLDB Endow.",
+//       "aliases": ["PVLDB"]
+//     }
+//   ]
+//
+// Names are compared by their words only, ignoring case, braces and
+// punctuation, so "Proc. {VLDB} Endow." is the abbreviation above.
+
+/// Fields naming the venue of a paper.
+pub const VENUE_FIELDS: [&str; 2] = ["journal", "booktitle"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VenueStyle {
+    Full,
+    Abbrev,
+}
+
+impl FromStr for VenueStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<VenueStyle, String> {
+        match s {
+            "full" => Ok(VenueStyle::Full),
+            "abbrev" => Ok(VenueStyle::Abbrev),
+            _ => Err(format!("Unknown venue style: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Venue {
+    pub full: String,
+    pub abbrev: String,
+    pub aliases: Vec<String>,
+}
+
+impl Venue {
+    fn names(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.full).chain(std::iter::once(&self.abbrev)).chain(self.aliases.iter())
+    }
+
+    pub fn name(&self, style: VenueStyle) -> &str {
+        match style {
+            VenueStyle::Full => &self.full,
+            VenueStyle::Abbrev => &self.abbrev,
+        }
+    }
+}
+
+pub struct Venues {
+    venues: Vec<Venue>,
+}
+
+fn name_key(name: &str) -> String {
+    string_cleaner::tokenize(&string_cleaner::clean_string(name)).join(" ")
+}
+
+impl Venues {
+    pub fn from_json(venues: &json::JsonValue) -> Venues {
+        let venues = venues.members().map(|venue| {
+            let full = venue["full"].as_str().expect("Venue without a full name").to_string();
+            Venue {
+                abbrev: venue["abbrev"].as_str().unwrap_or(&full).to_string(),
+                aliases: venue["aliases"].members().filter_map(|alias| alias.as_str()).map(String::from).collect(),
+                full,
+            }
+        }).collect();
+        Venues { venues }
+    }
+
+    pub fn load(path: &Path) -> Venues {
+        match std::fs::read_to_string(path) {
+            Ok(venues) => Venues::from_json(&json::parse(&venues).expect("Failed to parse venues file")),
+            Err(error) => match error.kind() {
+                std::io::ErrorKind::NotFound => Venues { venues: Vec::new() },
+                _ => panic!("Could not open venues file: {:?}", error),
+            }
+        }
+    }
+
+    /// The venue going by a name.
+    pub fn find(&self, name: &str) -> Option<&Venue> {
+        let key = name_key(name);
+        if key.is_empty() {
+            return None
+        }
+        return self.venues.iter().find(|venue| venue.names().any(|name| name_key(name) == key))
+    }
+
+    /// Replace the venues of the papers with their full names or
+    /// abbreviations. Unknown venues are kept as they are.
+    pub fn rename(&self, mut selection: json::JsonValue, style: VenueStyle) -> json::JsonValue {
+        for (_, paper) in selection.entries_mut() {
+            for field in VENUE_FIELDS.iter() {
+                let venue = paper[*field].as_str().and_then(|name| self.find(name));
+                if let Some(venue) = venue {
+                    paper[*field] = json::from(venue.name(style));
+                }
+            }
+        }
+        return selection
+    }
+
+    /// Whether a paper was published in the venue going by a name, in the
+    /// given venue field or any of them.
+    pub fn published_in(&self, paper: &json::JsonValue, field: Option<&str>, name: &str) -> bool {
+        let venue = match self.find(name) {
+            Some(venue) => venue,
+            None => return false,
+        };
+        return VENUE_FIELDS.iter()
+            .filter(|venue_field| field.is_none_or(|field| field == **venue_field))
+            .filter_map(|venue_field| paper[*venue_field].as_str())
+            .any(|paper_venue| self.find(paper_venue) == Some(venue))
+    }
+}
+
+#[cfg(test)]
+fn sample_venues() -> Venues {
+    Venues::from_json(&json::array![
+        {"full": "Proceedings of the VLDB Endowment", "abbrev": "Proc. VLDB Endow.", "aliases": ["PVLDB"]},
+        {"full": "International Conference on Machine Learning", "abbrev": "Int. Conf. Mach. Learn.", "aliases": ["ICML"]},
+    ])
+}
+
+#[test]
+fn find_by_any_name() {
+    let venues = sample_venues();
+    let vldb = Some(&venues.venues[0]);
+    assert_eq!(venues.find("PVLDB"), vldb);
+    assert_eq!(venues.find("Proc. {VLDB} Endow."), vldb);
+    assert_eq!(venues.find("proceedings of the  VLDB endowment"), vldb);
+    assert_eq!(venues.find("VLDB"), None);
+}
+
+#[test]
+fn rename_venues() {
+    let selection = json::object!{
+        "a": {"journal": "Proc. {VLDB} Endow."},
+        "b": {"booktitle": "ICML"},
+        "c": {"journal": "Unknown Journal"},
+    };
+    let renamed = sample_venues().rename(selection, VenueStyle::Full);
+    assert_eq!(renamed["a"]["journal"], "Proceedings of the VLDB Endowment");
+    assert_eq!(renamed["b"]["booktitle"], "International Conference on Machine Learning");
+    assert_eq!(renamed["c"]["journal"], "Unknown Journal");
+    let abbreviated = sample_venues().rename(renamed, VenueStyle::Abbrev);
+    assert_eq!(abbreviated["a"]["journal"], "Proc. VLDB Endow.");
+}
+
+#[test]
+fn papers_published_in_venue() {
+    let venues = sample_venues();
+    let paper = json::object!{"journal": "Proc. {VLDB} Endow."};
+    assert!(venues.published_in(&paper, None, "PVLDB"));
+    assert!(venues.published_in(&paper, Some("journal"), "Proceedings of the VLDB Endowment"));
+    assert!(!venues.published_in(&paper, Some("booktitle"), "PVLDB"));
+    assert!(!venues.published_in(&paper, None, "ICML"));
+}
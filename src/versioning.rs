@@ -0,0 +1,263 @@
+use std::path::Path;
+use std::process::Command;
+use crate::timestamps;
+
+// With "git": true in the config, the library directory is a git repository.
+// The database is saved pretty-printed with papers and fields sorted, so
+// each change shows up as a small diff, and every change is committed.
+// The journal, the notes and the venue list are committed along with it.
+// Syncing merges the database of the remote per paper, and per field
+// within a paper changed on both sides. Other files are merged by git.
+
+/// Pretty-printed database with papers and fields in sorted order.
+pub fn stable_dump(db: &json::JsonValue) -> String {
+    let mut keys = db.entries().map(|(key, _)| key).collect::<Vec<_>>();
+    keys.sort_unstable();
+    let mut sorted = json::object!{};
+    for key in keys {
+        let paper = &db[key];
+        let mut fields = paper.entries().map(|(field, _)| field).collect::<Vec<_>>();
+        fields.sort_unstable();
+        let mut sorted_paper = json::object!{};
+        for field in fields {
+            sorted_paper[field] = paper[field].clone();
+        }
+        sorted[key] = sorted_paper;
+    }
+    return format!("{}\n", sorted.pretty(2))
+}
+
+fn run(dir: &Path, args: &[&str]) -> std::process::Output {
+    Command::new("git")
+        .arg("-C").arg(dir)
+        .args(args)
+        .output().expect("Failed to execute git")
+}
+
+/// Run git, panicking with its error output if it fails.
+fn git(dir: &Path, args: &[&str]) -> String {
+    let output = run(dir, args);
+    if !output.status.success() {
+        panic!("git {} failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr));
+    }
+    return String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+fn succeeds(dir: &Path, args: &[&str]) -> bool {
+    run(dir, args).status.success()
+}
+
+fn ensure_repository(dir: &Path) {
+    if !dir.join(".git").exists() {
+        git(dir, &["init", "--quiet"]);
+    }
+}
+
+/// Commit message summing up a journal operation.
+pub fn commit_message(operation: &json::JsonValue) -> String {
+    let count = operation["changes"].len();
+    let subject = format!(
+        "{} ({} paper{})",
+        operation["command"].as_str().filter(|command| !command.is_empty()).unwrap_or("Update library"),
+        count,
+        if count == 1 { "" } else { "s" }
+    );
+    let description = crate::journal::describe(operation);
+    let body = description.lines().skip(1).map(str::trim).collect::<Vec<_>>().join("\n");
+    return format!("{}\n\n{}", subject, body)
+}
+
+/// Commit the given files and directories of the library if they changed.
+/// Those not created yet are skipped.
+pub fn commit(dir: &Path, files: &[&str], message: &str) {
+    ensure_repository(dir);
+    let existing = files.iter().filter(|file| dir.join(file).exists()).copied().collect::<Vec<_>>();
+    if existing.is_empty() {
+        return
+    }
+    git(dir, &[&["add", "--all", "--"], existing.as_slice()].concat());
+    if succeeds(dir, &["diff", "--cached", "--quiet"]) {
+        return
+    }
+    git(dir, &["commit", "--quiet", "-m", message]);
+}
+
+fn load_revision(dir: &Path, revision: &str, file: &str) -> json::JsonValue {
+    let output = run(dir, &["show", &format!("{}:{}", revision, file)]);
+    if !output.status.success() {
+        return json::object!{}
+    }
+    return json::parse(&String::from_utf8_lossy(&output.stdout)).expect("Failed to parse database in git history")
+}
+
+/// Three-way merge of two versions of the database. A paper changed on
+/// only one side takes that change, and a paper changed on both sides is
+/// merged field by field. Fields changed differently on both sides keep
+/// our value and are reported as conflicts, except for the timestamps kept
+/// on each paper, which take the latest.
+pub fn merge(base: &json::JsonValue, ours: &json::JsonValue, theirs: &json::JsonValue) -> (json::JsonValue, Vec<String>) {
+    let mut merged = json::object!{};
+    let mut conflicts = Vec::new();
+    let mut keys = ours.entries().map(|(key, _)| key).collect::<Vec<_>>();
+    keys.extend(theirs.entries().map(|(key, _)| key).filter(|key| !ours.has_key(key)));
+    for key in keys {
+        let (base_paper, our_paper, their_paper) = (&base[key], &ours[key], &theirs[key]);
+        let paper = if our_paper == their_paper || their_paper == base_paper {
+            our_paper.clone()
+        } else if our_paper == base_paper {
+            their_paper.clone()
+        } else if our_paper.is_null() || their_paper.is_null() {
+            conflicts.push(format!("{}: removed on one side and changed on the other, kept the changes", key));
+            if our_paper.is_null() { their_paper.clone() } else { our_paper.clone() }
+        } else {
+            let mut paper = our_paper.clone();
+            let mut fields = our_paper.entries().map(|(field, _)| field).collect::<Vec<_>>();
+            fields.extend(their_paper.entries().map(|(field, _)| field).filter(|field| !our_paper.has_key(field)));
+            for field in fields {
+                let (base_value, our_value, their_value) = (&base_paper[field], &our_paper[field], &their_paper[field]);
+                if our_value == their_value || their_value == base_value {
+                    continue
+                }
+                if field == timestamps::MODIFIED || field == timestamps::LAST_OPENED {
+                    // Bookkeeping timestamps take the latest value instead of conflicting.
+                    if their_value.as_str() > our_value.as_str() {
+                        paper[field] = their_value.clone();
+                    }
+                } else if our_value == base_value {
+                    match their_value {
+                        json::JsonValue::Null => { paper.remove(field); },
+                        value => paper[field] = value.clone(),
+                    }
+                } else {
+                    conflicts.push(format!("{}: kept local {}", key, field));
+                }
+            }
+            paper
+        };
+        if !paper.is_null() {
+            merged[key] = paper;
+        }
+    }
+    return (merged, conflicts)
+}
+
+/// Pull from and push to a remote, given by name or path. A database file
+/// to merge is merged per paper when both sides changed, anything else is
+/// left to git to merge.
+pub fn sync(dir: &Path, remote: &str, merge_file: Option<&str>) {
+    ensure_repository(dir);
+    let branch = git(dir, &["symbolic-ref", "--short", "HEAD"]);
+    if succeeds(dir, &["fetch", "--quiet", remote, &branch]) {
+        let has_head = succeeds(dir, &["rev-parse", "--verify", "--quiet", "HEAD"]);
+        if !has_head || succeeds(dir, &["merge-base", "--is-ancestor", "HEAD", "FETCH_HEAD"]) {
+            git(dir, &["merge", "--quiet", "--ff-only", "FETCH_HEAD"]);
+        } else if succeeds(dir, &["merge-base", "--is-ancestor", "FETCH_HEAD", "HEAD"]) {
+            // Nothing new on the remote.
+        } else if let Some(file) = merge_file {
+            // Libraries started separately have no common history to merge from.
+            let base = match run(dir, &["merge-base", "HEAD", "FETCH_HEAD"]) {
+                output if output.status.success() => load_revision(dir, String::from_utf8_lossy(&output.stdout).trim(), file),
+                _ => json::object!{},
+            };
+            let (merged, conflicts) = merge(
+                &base,
+                &load_revision(dir, "HEAD", file),
+                &load_revision(dir, "FETCH_HEAD", file),
+            );
+            // Git merges the other files, such as the journal, and leaves
+            // the database conflicting when both sides changed it.
+            let output = run(dir, &["merge", "--quiet", "--no-ff", "--no-commit", "--allow-unrelated-histories", "FETCH_HEAD"]);
+            if !succeeds(dir, &["rev-parse", "--quiet", "--verify", "MERGE_HEAD"]) {
+                panic!("git merge failed: {}", String::from_utf8_lossy(&output.stderr));
+            }
+            std::fs::write(dir.join(file), stable_dump(&merged)).expect("Could not save database file");
+            git(dir, &["add", "--", file]);
+            let unmerged = git(dir, &["diff", "--name-only", "--diff-filter=U"]);
+            if !unmerged.is_empty() {
+                eprintln!(
+                    "Could not merge {} from {}, resolve the conflicts and commit the merge before syncing again",
+                    unmerged.lines().collect::<Vec<_>>().join(", "), remote
+                );
+                std::process::exit(1);
+            }
+            let mut message = format!("Merge {} into {}", remote, branch);
+            if !conflicts.is_empty() {
+                message = format!("{}\n\nConflicts:\n{}", message, conflicts.join("\n"));
+            }
+            git(dir, &["commit", "--quiet", "-m", &message]);
+            for conflict in conflicts {
+                println!("Conflict in {}", conflict);
+            }
+        } else {
+            git(dir, &["merge", "--quiet", "--no-edit", "--allow-unrelated-histories", "FETCH_HEAD"]);
+        }
+    }
+    git(dir, &["push", "--quiet", remote, &format!("HEAD:{}", branch)]);
+}
+
+#[test]
+fn stable_dump_sorts_papers_and_fields() {
+    let db = json::object!{"b": {"year": "2017", "author": "Lyu"}, "a": {"title": "T"}};
+    assert_eq!(
+        stable_dump(&db),
+        "{\n  \"a\": {\n    \"title\": \"T\"\n  },\n  \"b\": {\n    \"author\": \"Lyu\",\n    \"year\": \"2017\"\n  }\n}\n"
+    );
+}
+
+#[test]
+fn merge_papers_changed_on_one_side() {
+    let base = json::object!{"a": {"title": "A"}, "b": {"title": "B"}, "c": {"title": "C"}};
+    let ours = json::object!{"a": {"title": "A2"}, "b": {"title": "B"}, "d": {"title": "D"}};
+    let theirs = json::object!{"a": {"title": "A"}, "b": {"title": "B2"}, "c": {"title": "C"}, "e": {"title": "E"}};
+    let (merged, conflicts) = merge(&base, &ours, &theirs);
+    assert_eq!(merged, json::object!{"a": {"title": "A2"}, "b": {"title": "B2"}, "d": {"title": "D"}, "e": {"title": "E"}});
+    assert!(conflicts.is_empty());
+}
+
+#[test]
+fn merge_fields_of_paper_changed_on_both_sides() {
+    let base = json::object!{"a": {"title": "A", "year": "2017", "note": "x", "modified": "2026-01-01T00:00:00Z"}};
+    let ours = json::object!{"a": {"title": "Ours", "year": "2018", "note": "x", "modified": "2026-01-02T00:00:00Z"}};
+    let theirs = json::object!{"a": {"title": "Theirs", "year": "2017", "tags": ["dp"], "modified": "2026-01-03T00:00:00Z"}};
+    let (merged, conflicts) = merge(&base, &ours, &theirs);
+    assert_eq!(merged, json::object!{"a": {"title": "Ours", "year": "2018", "modified": "2026-01-03T00:00:00Z", "tags": ["dp"]}});
+    assert_eq!(conflicts, vec!["a: kept local title"]);
+}
+
+#[cfg(test)]
+fn test_repository(dir: &Path) {
+    std::fs::create_dir_all(dir).unwrap();
+    git(dir, &["init", "--quiet"]);
+    git(dir, &["config", "user.name", "Paperman"]);
+    git(dir, &["config", "user.email", "paperman@example.com"]);
+}
+
+#[test]
+fn sync_notes_between_clones() {
+    let root = std::env::temp_dir().join(format!("paperman-sync-{}", std::process::id()));
+    let (remote, first, second) = (root.join("remote"), root.join("first"), root.join("second"));
+    std::fs::create_dir_all(&remote).unwrap();
+    git(&remote, &["init", "--quiet", "--bare"]);
+    let remote = remote.to_string_lossy().to_string();
+    let files = ["db.json", "journal.jsonl", "notes", "venues.json"];
+    test_repository(&first);
+    test_repository(&second);
+    // Both clones push to and pull from the same branch of the remote.
+    git(&second, &["symbolic-ref", "HEAD", &git(&first, &["symbolic-ref", "HEAD"])]);
+
+    std::fs::write(first.join("db.json"), stable_dump(&json::object!{"a": {"title": "T"}})).unwrap();
+    std::fs::create_dir_all(first.join("notes")).unwrap();
+    std::fs::write(first.join("notes").join("a.md"), "First note\n").unwrap();
+    commit(&first, &files, "Add a");
+    sync(&first, &remote, Some("db.json"));
+    sync(&second, &remote, Some("db.json"));
+    assert_eq!(std::fs::read_to_string(second.join("notes").join("a.md")).unwrap(), "First note\n");
+
+    std::fs::write(second.join("notes").join("a.md"), "Second note\n").unwrap();
+    commit(&second, &files, "Edit note");
+    sync(&second, &remote, Some("db.json"));
+    sync(&first, &remote, Some("db.json"));
+    let note = std::fs::read_to_string(first.join("notes").join("a.md")).unwrap();
+    std::fs::remove_dir_all(&root).unwrap();
+    assert_eq!(note, "Second note\n");
+}